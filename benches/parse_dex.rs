@@ -0,0 +1,214 @@
+// Parses a large synthetic dex through process_vm_readv and compares it with
+// the cost of the one-syscall-per-byte access pattern the parser used to have.
+//
+//   cargo bench --bench parse_dex -- [classes] [methods_per_class]
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use zygisk_rust_dex_unpacker::dex_parser;
+use zygisk_rust_dex_unpacker::memory_reader::{MemoryReader, ProcessVmReader};
+
+const HEADER_SIZE: usize = 0x70;
+const ITERATIONS: u32 = 5;
+
+/// Counts `read_memory` calls, i.e. syscalls for `ProcessVmReader`.
+struct CountingReader<R> {
+    inner: R,
+    calls: AtomicUsize,
+}
+
+impl<R: MemoryReader> MemoryReader for CountingReader<R> {
+    fn read_memory(&self, addr: usize, buf: &mut [u8]) -> io::Result<usize> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.inner.read_memory(addr, buf)
+    }
+}
+
+struct SyntheticDex {
+    data: Vec<u8>,
+    /// Bytes the old parser fetched one syscall at a time (string data + class data)
+    byte_reads: usize,
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn align4(out: &mut Vec<u8>) {
+    while !out.len().is_multiple_of(4) {
+        out.push(0);
+    }
+}
+
+fn put_u32(out: &mut [u8], offset: usize, value: u32) {
+    out[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// `classes` classes with `methods` direct `void mN()` methods each, every one with a code item.
+fn build_dex(classes: usize, methods: usize) -> SyntheticDex {
+    let mut strings = vec!["V".to_string()];
+    strings.extend((0..classes).map(|c| format!("Lcom/example/bench/Class{};", c)));
+    strings.extend((0..methods).map(|m| format!("method{}", m)));
+
+    let string_ids_off = HEADER_SIZE;
+    let type_ids_off = string_ids_off + strings.len() * 4;
+    let type_count = 1 + classes;
+    let proto_ids_off = type_ids_off + type_count * 4;
+    let method_ids_off = proto_ids_off + 12;
+    let method_count = classes * methods;
+    let class_defs_off = method_ids_off + method_count * 8;
+    let data_off = class_defs_off + classes * 32;
+
+    let mut dex = vec![0u8; data_off];
+    let mut byte_reads = 0;
+
+    // string_data_item
+    for (i, s) in strings.iter().enumerate() {
+        let start = dex.len();
+        put_u32(&mut dex, string_ids_off + i * 4, start as u32);
+        write_uleb128(&mut dex, s.len() as u32);
+        dex.extend_from_slice(s.as_bytes());
+        dex.push(0);
+        byte_reads += dex.len() - start;
+    }
+
+    // type_ids: 0 = V, 1.. = classes
+    for t in 0..type_count {
+        put_u32(&mut dex, type_ids_off + t * 4, t as u32);
+    }
+
+    // proto_ids[0] = ()V
+    put_u32(&mut dex, proto_ids_off, 0);
+    put_u32(&mut dex, proto_ids_off + 4, 0);
+    put_u32(&mut dex, proto_ids_off + 8, 0);
+
+    for c in 0..classes {
+        for m in 0..methods {
+            let off = method_ids_off + (c * methods + m) * 8;
+            dex[off..off + 2].copy_from_slice(&((1 + c) as u16).to_le_bytes());
+            dex[off + 2..off + 4].copy_from_slice(&0u16.to_le_bytes());
+            put_u32(&mut dex, off + 4, (1 + classes + m) as u32);
+        }
+    }
+
+    // code_item: registers=1, ins=0, outs=0, tries=0, debug=0, insns=[return-void]
+    align4(&mut dex);
+    let mut code_offs = Vec::with_capacity(method_count);
+    for _ in 0..method_count {
+        code_offs.push(dex.len() as u32);
+        dex.extend_from_slice(&1u16.to_le_bytes());
+        dex.extend_from_slice(&[0u8; 6]);
+        dex.extend_from_slice(&0u32.to_le_bytes());
+        dex.extend_from_slice(&1u32.to_le_bytes());
+        dex.extend_from_slice(&0x000eu16.to_le_bytes());
+        align4(&mut dex);
+    }
+
+    // class_data_item + class_def_item
+    for c in 0..classes {
+        let class_data_off = dex.len();
+        write_uleb128(&mut dex, 0);
+        write_uleb128(&mut dex, 0);
+        write_uleb128(&mut dex, methods as u32);
+        write_uleb128(&mut dex, 0);
+        for m in 0..methods {
+            let diff = if m == 0 { c * methods } else { 1 };
+            write_uleb128(&mut dex, diff as u32);
+            write_uleb128(&mut dex, 0x0009); // public static
+            write_uleb128(&mut dex, code_offs[c * methods + m]);
+        }
+        byte_reads += dex.len() - class_data_off;
+
+        let def = class_defs_off + c * 32;
+        put_u32(&mut dex, def, (1 + c) as u32);
+        put_u32(&mut dex, def + 4, 0x0001);
+        put_u32(&mut dex, def + 8, u32::MAX); // no superclass
+        put_u32(&mut dex, def + 16, u32::MAX); // no source file
+        put_u32(&mut dex, def + 24, class_data_off as u32);
+    }
+
+    // map_list (header only, enough for the scanner heuristics)
+    align4(&mut dex);
+    let map_off = dex.len();
+    dex.extend_from_slice(&1u32.to_le_bytes());
+    dex.extend_from_slice(&0u16.to_le_bytes());
+    dex.extend_from_slice(&0u16.to_le_bytes());
+    dex.extend_from_slice(&1u32.to_le_bytes());
+    dex.extend_from_slice(&0u32.to_le_bytes());
+
+    let file_size = dex.len();
+    dex[0..8].copy_from_slice(b"dex\n035\0");
+    let fields = [
+        (0x20, file_size), (0x24, HEADER_SIZE), (0x28, 0x12345678), (0x34, map_off),
+        (0x38, strings.len()), (0x3C, string_ids_off),
+        (0x40, type_count), (0x44, type_ids_off),
+        (0x48, 1), (0x4C, proto_ids_off),
+        (0x58, method_count), (0x5C, method_ids_off),
+        (0x60, classes), (0x64, class_defs_off),
+        (0x68, file_size - data_off), (0x6C, data_off),
+    ];
+    for (off, value) in fields {
+        put_u32(&mut dex, off, value as u32);
+    }
+
+    SyntheticDex { data: dex, byte_reads }
+}
+
+fn best_of<F: FnMut()>(mut f: F) -> Duration {
+    (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let args: Vec<usize> = std::env::args().skip(1).filter_map(|a| a.parse().ok()).collect();
+    let classes = args.first().copied().unwrap_or(2000);
+    let methods = args.get(1).copied().unwrap_or(30);
+
+    let dex = build_dex(classes, methods);
+    let addr = dex.data.as_ptr() as usize;
+    println!(
+        "synthetic dex: {} bytes, {} classes, {} methods",
+        dex.data.len(), classes, classes * methods
+    );
+
+    let reader = CountingReader {
+        inner: ProcessVmReader::new(std::process::id() as libc::pid_t),
+        calls: AtomicUsize::new(0),
+    };
+
+    let parsed = dex_parser::parse_dex_at(&reader, addr).expect("synthetic dex must parse");
+    assert_eq!(parsed.methods.len(), classes * methods);
+    assert!(parsed.methods.iter().all(|m| m.code_item.is_some()));
+    let syscalls = reader.calls.swap(0, Ordering::Relaxed);
+
+    let bulk = best_of(|| {
+        dex_parser::parse_dex_at(&reader, addr).unwrap();
+    });
+
+    // What the per-byte reads alone used to cost, without any of the decoding work
+    let mut byte = [0u8; 1];
+    let per_byte = best_of(|| {
+        for i in 0..dex.byte_reads {
+            reader.inner.read_memory(addr + i % dex.data.len(), &mut byte).unwrap();
+        }
+    });
+
+    println!("bulk parse:        {:>10.2?} ({} syscalls)", bulk, syscalls);
+    println!("per-byte reads:    {:>10.2?} ({} syscalls, reads only)", per_byte, dex.byte_reads);
+    println!("speedup:           {:>10.1}x", per_byte.as_secs_f64() / bulk.as_secs_f64());
+}
@@ -3,6 +3,7 @@ use super::dex_parser::ParsedMethod;
 use jni::objects::{GlobalRef, JClass, JObject, JValue};
 use jni::JavaVM;
 use log::{debug, error, info};
+use serde::Serialize;
 use std::collections::HashSet;
 
 fn get_system_classloader<'a>(env: &mut jni::JNIEnv<'a>) -> anyhow::Result<JObject<'a>> {
@@ -103,21 +104,37 @@ fn should_skip_class(binary_name: &str) -> bool {
     false
 }
 
+/// Counters of one `force_resolve_methods` run, for the session manifest.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResolveStats {
+    pub target_methods: usize,
+    pub resolved: u32,
+    pub skipped_system: u32,
+    pub class_not_found: u32,
+    pub class_not_found_simple: u32,
+    pub method_not_found: u32,
+    /// false if a fatal JNI error aborted the run
+    pub completed: bool,
+}
+
 // --- Main Function ---
 
 /// Iterates through a list of parsed methods and attempts to resolve them using JNI.
 /// This forces the ART runtime to load and prepare the method, often triggering
 /// `GetCodeItem` and causing lazy-compiled code to be fully unpacked.
-pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
+pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) -> ResolveStats {
+    let mut stats = ResolveStats::default();
     // FILTER: Only resolve methods that actually have code to unpack.
     // This skips imported methods (references to other DEX files) which cause
     // most ClassNotFound errors.
     let target_methods: Vec<&ParsedMethod> = methods.iter()
         .filter(|m| m.code_item.is_some()) 
         .collect();
+    stats.target_methods = target_methods.len();
     if target_methods.is_empty() {
         info!("[ArtRuntime] No methods with CodeItems found to resolve.");
-        return;
+        stats.completed = true;
+        return stats;
     }
     info!("[ArtRuntime] Attaching to VM to resolve {} defined methods (out of {} total refs)...", target_methods.len(), methods.len());
 
@@ -125,7 +142,7 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
         Ok(env) => env,
         Err(e) => {
             error!("[ArtRuntime] Failed to attach to JNIEnv: {:?}", e);
-            return;
+            return stats;
         }
     };
 
@@ -138,7 +155,7 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
             } else {
                 error!("[ArtRuntime] Cannot find java.lang.Class. Aborting.");
             }
-            return;
+            return stats;
         }
     };
 
@@ -149,13 +166,13 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
     match get_app_classloader(&mut env) {
         Ok(loader) => {
             if add_loader_to_list(&mut env, &mut loaders, loader) {
-                return; // Fatal error
+                return stats; // Fatal error
             }
         }
         Err(_) => {
             if env.exception_clear().is_err() {
                 error!("[ArtRuntime] Fatal JNI error getting system loader. Aborting.");
-                return;
+                return stats;
             }
         }
     }
@@ -163,13 +180,13 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
     match get_system_classloader(&mut env) {
         Ok(loader) => {
             if add_loader_to_list(&mut env, &mut loaders, loader) {
-                return; // Fatal error
+                return stats; // Fatal error
             } 
         }
         Err(_) => {
             if env.exception_clear().is_err() {
                 error!("[ArtRuntime] Fatal JNI error getting system loader. Aborting.");
-                return;
+                return stats;
             }
         }
     }
@@ -177,11 +194,6 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
     info!("[ArtRuntime] Found {} classloaders to try.", loaders.len());
 
     // --- Main Method Resolution Loop ---
-    let mut skipped_system_count: u32 = 0;
-    let mut resolved_count:u32 = 0;
-    let mut class_not_found_count:u32 = 0;
-    let mut class_not_found_simple_count:u32 = 0;
-    let mut method_not_found_count:u32 = 0;
     let mut seen = HashSet::<(String, String, String)>::new();
 
     for (i, method) in target_methods.iter().enumerate() {
@@ -206,7 +218,7 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
         // *** FIX: Use the descriptor_to_binary_name function correctly ***
         if let Some(class_name_jni) = descriptor_to_binary_name(&method.class_name) {
             if should_skip_class(&class_name_jni) {
-                skipped_system_count += 1;
+                stats.skipped_system += 1;
                 continue; 
             }
             // --- Try to find the class via several strategies ---
@@ -222,9 +234,9 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
                 Err(_) => {
                     if env.exception_clear().is_err() {
                         error!("[ArtRuntime] Fatal JNI error in find_class. Aborting.");
-                        return;
+                        return stats;
                     }
-                    class_not_found_simple_count += 1;
+                    stats.class_not_found_simple += 1;
                 }
             };
 
@@ -236,7 +248,7 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
                     let jname = match env.new_string(&class_name_jni) {
                         Ok(s) => s,
                         Err(_) => {
-                            if env.exception_clear().is_err() { return; }
+                            if env.exception_clear().is_err() { return stats; }
                             continue;
                         }
                     };
@@ -258,7 +270,7 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
                             }
                         }
                         Err(_) => {
-                            if env.exception_clear().is_err() { return; }
+                            if env.exception_clear().is_err() { return stats; }
                         }
                     }
                 }
@@ -272,7 +284,7 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
                         let jname = match env.new_string(&class_name_jni) {
                             Ok(s) => s,
                             Err(_) => {
-                                if env.exception_clear().is_err() { return; }
+                                if env.exception_clear().is_err() { return stats; }
                                 continue;
                             }
                         };
@@ -302,7 +314,7 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
                                 }
                             }
                             Err(_) => {
-                                if env.exception_clear().is_err() { return; }
+                                if env.exception_clear().is_err() { return stats; }
                             }
                         }
                     }
@@ -311,7 +323,7 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
 
             if found_jclass.is_none() {
                 debug!("[ArtRuntime] class not found for descriptor {} (binary {})", method.class_name, class_name_jni);
-                class_not_found_count += 1;
+                stats.class_not_found += 1;
                 continue;
             }
 
@@ -321,13 +333,13 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
             // Try instance method
             match env.get_method_id(&jclass, &method.method_name, &method.signature) {
                 Ok(_) => {
-                    resolved_count += 1;
+                    stats.resolved += 1;
                     continue; // Found it, we're done.
                 }
                 Err(_) => {
                     if env.exception_clear().is_err() {
                         error!("[ArtRuntime] Fatal JNI error in get_method_id. Aborting.");
-                        return;
+                        return stats;
                     }
                 }
             }
@@ -335,30 +347,33 @@ pub fn force_resolve_methods(vm: &JavaVM, methods: &Vec<ParsedMethod>) {
             // Try static method
             match env.get_static_method_id(&jclass, &method.method_name, &method.signature) {
                 Ok(_) => {
-                    resolved_count += 1;
+                    stats.resolved += 1;
                     if env.exception_clear().is_err() {
                         error!("[ArtRuntime] Fatal JNI error after get_static_method_id. Aborting.");
-                        return;
+                        return stats;
                     }
                 }
                 Err(_) => {
                     if env.exception_clear().is_err() {
                         error!("[ArtRuntime] Fatal JNI error in get_static_method_id. Aborting.");
-                        return;
+                        return stats;
                     }
-                    method_not_found_count += 1;
+                    stats.method_not_found += 1;
                 }
             }
         } else {
              // descriptor_to_binary_name returned None
-            class_not_found_count += 1;
+            stats.class_not_found += 1;
         }
     }
 
     info!("[ArtRuntime] --- Method Resolution Complete ---");
-    info!("[ArtRuntime] Succeeded: {}", resolved_count);
-    info!("[ArtRuntime] Class System Skipped: {}, Class Not Found (Skipped): {}, Class Not Found by env-findclass {}", skipped_system_count ,class_not_found_count, class_not_found_simple_count);
-    info!("[ArtRuntime] Method Not Found (Skipped): {}", method_not_found_count);
+    info!("[ArtRuntime] Succeeded: {}", stats.resolved);
+    info!("[ArtRuntime] Class System Skipped: {}, Class Not Found (Skipped): {}, Class Not Found by env-findclass {}", stats.skipped_system ,stats.class_not_found, stats.class_not_found_simple);
+    info!("[ArtRuntime] Method Not Found (Skipped): {}", stats.method_not_found);
+
+    stats.completed = true;
+    stats
 }
 
 
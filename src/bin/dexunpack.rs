@@ -0,0 +1,533 @@
+// dexunpack: run the scanner/parser/dumper against any process we may ptrace,
+// without a Zygisk-enabled device.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use zygisk_rust_dex_unpacker::control;
+use zygisk_rust_dex_unpacker::config::PackageConfig;
+use zygisk_rust_dex_unpacker::dex_parser;
+use zygisk_rust_dex_unpacker::dex_scanner::{self, DexFileResult, PointerWidth, ScanOptions, ScanStats};
+use zygisk_rust_dex_unpacker::dump_store::{self, DumpSink, DumpStore, SessionDir};
+use zygisk_rust_dex_unpacker::manifest::SessionManifest;
+use zygisk_rust_dex_unpacker::maps::MapInfo;
+use zygisk_rust_dex_unpacker::memory_reader::{MemoryReader, ProcMemReader, ProcessVmReader};
+use zygisk_rust_dex_unpacker::scheduler::Schedule;
+use zygisk_rust_dex_unpacker::session::{self, ScanSession, SeenDex};
+use zygisk_rust_dex_unpacker::snapshot::{self, RawLayout};
+
+const USAGE: &str = "\
+Usage: dexunpack <command> [options]
+
+Commands:
+  scan  --pid <pid>                    List DEX files found in memory
+  dump  --pid <pid> --out <dir>        Scan and save every DEX found
+  parse --pid <pid> --addr <hex> [--classes]
+                                       Parse the DEX at <addr> and print a summary,
+                                       or every class with its fields and methods
+  ctl <scan|dump|resolve|status|list> --pid <pid> [--package <name>]
+                                       Send a command to the control socket of a
+                                       running scanner (module or dump --control)
+  offline --core <file> [--out <dir>]  Scan an ELF core file
+  offline --image <file> --maps <file> [--sparse] [--out <dir>]
+                                       Scan a raw memory image plus its saved maps;
+                                       regions are back to back unless --sparse
+                                       (file offset == address)
+
+Options:
+  --no-deep           Magic scan only, skip the pointer (deep) search
+  --include-system    Also report framework and apex dex, skipped by default
+  --tag-mask <hex>    Pointer tag bits cleared before lookup (default ff00000000000000, 0 = none)
+  --threads <n>       Scan worker threads (default half the CPUs)
+  --cpu-percent <p>   Share of one CPU each worker may use, 1-100 (default 100)
+  --reconstruct       Rebuild wiped headers (file_size, map_off, id offsets) from the
+                      map_list or the string_ids/type_ids tables
+  --pointer-size <4|8>
+                      Pointer size of the target, detected from its executable or core
+                      file, native for raw images
+  --reader <vm|mem>   Read memory with process_vm_readv (default) or /proc/<pid>/mem
+  --keep <n>          Dump sessions kept under --out (default 5)
+  --rounds <n>        dump: scan n times, saving only dex not seen in an earlier round
+  --interval <secs>   dump: wait between rounds (default 30)
+  --control           dump: serve the control socket, keep running after the rounds
+  --socket <name>     ctl: abstract socket name instead of the one for --pid/--package
+  -v, --verbose       Print library logs to stderr
+";
+
+/// Prints library `log` output to stderr, the host has no logcat.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        eprintln!("[{}] {}", record.level(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+#[derive(Default)]
+struct Options {
+    pid: Option<libc::pid_t>,
+    out: Option<String>,
+    addr: Option<usize>,
+    core: Option<String>,
+    image: Option<String>,
+    maps: Option<String>,
+    sparse: bool,
+    deep_search: bool,
+    include_system: bool,
+    pointer_tag_mask: u64,
+    pointer_width: Option<PointerWidth>,
+    threads: usize,
+    cpu_percent: u32,
+    reconstruct: bool,
+    use_proc_mem: bool,
+    keep_sessions: usize,
+    rounds: u32,
+    interval_secs: u64,
+    control: bool,
+    package: Option<String>,
+    socket: Option<String>,
+    /// Words that are not options, e.g. the command sent by `ctl`
+    positional: Vec<String>,
+    classes: bool,
+    verbose: bool,
+}
+
+fn parse_addr(s: &str) -> Result<usize, String> {
+    let hex = s.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(hex, 16).map_err(|e| format!("invalid address '{}': {}", s, e))
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        deep_search: true,
+        pointer_tag_mask: dex_scanner::DEFAULT_POINTER_TAG_MASK,
+        cpu_percent: 100,
+        keep_sessions: dump_store::DEFAULT_KEEP_SESSIONS,
+        rounds: 1,
+        interval_secs: 30,
+        ..Default::default()
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--pid" => {
+                let v = value()?;
+                opts.pid = Some(v.parse().map_err(|e| format!("invalid pid '{}': {}", v, e))?);
+            }
+            "--out" => opts.out = Some(value()?.clone()),
+            "--addr" => opts.addr = Some(parse_addr(value()?)?),
+            "--core" => opts.core = Some(value()?.clone()),
+            "--image" => opts.image = Some(value()?.clone()),
+            "--maps" => opts.maps = Some(value()?.clone()),
+            "--sparse" => opts.sparse = true,
+            "--reader" => match value()?.as_str() {
+                "vm" => opts.use_proc_mem = false,
+                "mem" => opts.use_proc_mem = true,
+                other => return Err(format!("unknown reader '{}'", other)),
+            },
+            "--keep" => {
+                let v = value()?;
+                opts.keep_sessions = match v.parse() {
+                    Ok(n @ 1..) => n,
+                    _ => return Err(format!("invalid session count '{}', expected at least 1", v)),
+                };
+            }
+            "--rounds" => {
+                let v = value()?;
+                opts.rounds = v.parse().map_err(|e| format!("invalid round count '{}': {}", v, e))?;
+            }
+            "--interval" => {
+                let v = value()?;
+                opts.interval_secs = v.parse().map_err(|e| format!("invalid interval '{}': {}", v, e))?;
+            }
+            "--control" => opts.control = true,
+            "--package" => opts.package = Some(value()?.clone()),
+            "--socket" => opts.socket = Some(value()?.clone()),
+            "--no-deep" => opts.deep_search = false,
+            "--include-system" => opts.include_system = true,
+            "--reconstruct" => opts.reconstruct = true,
+            "--threads" => {
+                let v = value()?;
+                opts.threads = v.parse().map_err(|e| format!("invalid thread count '{}': {}", v, e))?;
+            }
+            "--cpu-percent" => {
+                let v = value()?;
+                opts.cpu_percent = match v.parse() {
+                    Ok(p @ 1..=100) => p,
+                    _ => return Err(format!("invalid CPU percentage '{}', expected 1-100", v)),
+                };
+            }
+            "--pointer-size" => match value()?.as_str() {
+                "4" => opts.pointer_width = Some(PointerWidth::Bits32),
+                "8" => opts.pointer_width = Some(PointerWidth::Bits64),
+                other => return Err(format!("invalid pointer size '{}', expected 4 or 8", other)),
+            },
+            "--tag-mask" => {
+                let v = value()?;
+                let hex = v.trim_start_matches("0x").trim_start_matches("0X");
+                opts.pointer_tag_mask = u64::from_str_radix(hex, 16).map_err(|e| format!("invalid tag mask '{}': {}", v, e))?;
+            }
+            "--classes" => opts.classes = true,
+            "-v" | "--verbose" => opts.verbose = true,
+            other if !other.starts_with('-') => opts.positional.push(other.to_string()),
+            other => return Err(format!("unknown option '{}'", other)),
+        }
+    }
+    Ok(opts)
+}
+
+fn open_reader(pid: libc::pid_t, use_proc_mem: bool) -> Result<Box<dyn MemoryReader + Send>, String> {
+    if use_proc_mem {
+        let reader = ProcMemReader::open(pid).map_err(|e| format!("cannot open /proc/{}/mem: {}", pid, e))?;
+        Ok(Box::new(reader))
+    } else {
+        Ok(Box::new(ProcessVmReader::new(pid)))
+    }
+}
+
+fn print_result(i: usize, dex: &DexFileResult) {
+    println!(
+        "[{}] addr=0x{:x} size=0x{:x} ({}) version={} source={} class={:?}",
+        i, dex.addr, dex.size, dex.size, dex.version, dex.source, dex.classification
+    );
+    if let Some(map) = &dex.map {
+        println!("    in   {}", describe_map(map));
+    }
+    if let Some(pointer) = &dex.referenced_from {
+        let map = pointer.map.as_ref().map(describe_map).unwrap_or_default();
+        println!("    from {} (value {}) in {}", pointer.address, pointer.value, map);
+    }
+    if let Some(reconstruction) = &dex.reconstruction {
+        let fields: Vec<&str> = reconstruction.inferred_fields.iter().map(|f| f.field).collect();
+        println!(
+            "    header rebuilt from {:?}, confidence {:.2}, inferred {}",
+            reconstruction.method, reconstruction.confidence, fields.join(", ")
+        );
+        if !reconstruction.failed_checks.is_empty() {
+            println!("    failed checks: {}", reconstruction.failed_checks.join(", "));
+        }
+    }
+}
+
+fn print_stats(stats: &ScanStats) {
+    println!(
+        "Pointer candidates: {} ({} unreadable, {} cache hits), verifications: {}, hits: {} ({} rebuilt), workers: {}",
+        stats.pointer_candidates, stats.unreadable_targets, stats.cache_hits, stats.verifications, stats.hits, stats.reconstructed, stats.workers
+    );
+    for skipped in &stats.skipped_by_map {
+        println!("Skipped {} unreadable page(s) in {}", skipped.pages, describe_map(&skipped.map));
+    }
+}
+
+/// A class and its members, one per line.
+fn print_class(dex: &dex_parser::ParsedDexFile, class: &dex_parser::ParsedClass) {
+    println!(
+        "  class {} flags=0x{:x} extends {}{}{}",
+        class.descriptor,
+        class.access_flags,
+        class.superclass.as_deref().unwrap_or("-"),
+        if class.interfaces.is_empty() { String::new() } else { format!(" implements {}", class.interfaces.join(", ")) },
+        class.source_file.as_deref().map(|f| format!(" ({})", f)).unwrap_or_default()
+    );
+    if let Some(error) = &class.class_data_error {
+        println!("    class data: {}", error);
+    }
+    for field in class.static_fields.iter().chain(&class.instance_fields) {
+        println!("    field  {}:{} flags=0x{:x}", field.name, field.type_name, field.access_flags);
+    }
+    for method in class.direct_methods.iter().chain(&class.virtual_methods) {
+        let (insns, tries) = dex.code_item(method.method_idx).map_or((0, 0), |code| (code.insns.len(), code.tries.len()));
+        println!("    method {}{} flags=0x{:x} insns={} tries={}", method.name, method.signature, method.access_flags, insns, tries);
+        if let Some(error) = &method.code_error {
+            println!("      code item: {}", error);
+        }
+    }
+}
+
+/// One line like in `/proc/<pid>/maps`.
+fn describe_map(map: &MapInfo) -> String {
+    format!(
+        "{}-{} {} {:x} {} {} {}",
+        map.start, map.end, map.perms, map.offset, map.dev, map.inode, map.path.as_deref().unwrap_or("")
+    )
+}
+
+/// Name of the process as in `ps`, e.g. the package name of an app.
+fn process_name(pid: libc::pid_t) -> String {
+    std::fs::read(format!("/proc/{}/cmdline", pid))
+        .ok()
+        .and_then(|cmdline| cmdline.split(|b| *b == 0).next().map(|s| String::from_utf8_lossy(s).into_owned()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| format!("pid-{}", pid))
+}
+
+/// Creates a new session under the dump store at `opts.out`.
+fn open_session(
+    package: &str,
+    pid: libc::pid_t,
+    opts: &Options,
+) -> Result<(DumpStore, SessionDir, SessionManifest), String> {
+    let out = opts.out.as_deref().ok_or("--out is required")?;
+    let store = DumpStore::new(out);
+    let dump_session = store.create_session(pid).map_err(|e| format!("cannot create session in {}: {}", out, e))?;
+    let manifest = SessionManifest::new(&dump_session.name, package, pid, session_config(opts));
+    Ok((store, dump_session, manifest))
+}
+
+/// Prints what the session dumped, writes its manifest and applies retention.
+fn finish_session(
+    store: &DumpStore,
+    dump_session: &dyn DumpSink,
+    manifest: &mut SessionManifest,
+    opts: &Options,
+) -> Result<(), String> {
+    for round in &manifest.rounds {
+        println!(
+            "Round {}: {} candidate(s), {} new, {} already seen",
+            round.round, round.candidates, round.new_dex, round.already_seen
+        );
+    }
+    for entry in &manifest.dex_files {
+        println!(
+            "[{}] addr={} size=0x{:x} ({}) version={} source={} round={}",
+            entry.index, entry.address, entry.size, entry.size, entry.version, entry.source, entry.round
+        );
+        match (&entry.file, &entry.parse_error, &entry.dump_error) {
+            (Some(file), _, _) => println!(
+                "    -> {} (0x{:x} bytes, {} hole(s), {} header fix(es){})",
+                file,
+                entry.dumped_size.unwrap_or(0),
+                entry.holes.len(),
+                entry.header_repairs.len(),
+                if entry.duplicate { ", seen before" } else { "" }
+            ),
+            (None, Some(e), _) => eprintln!("    failed to parse: {}", e),
+            (None, _, Some(e)) => eprintln!("    failed to dump: {}", e),
+            (None, None, None) => {}
+        }
+    }
+
+    manifest.finish();
+    let path = manifest.write_to(dump_session).map_err(|e| format!("cannot write manifest: {}", e))?;
+    println!("Manifest: {}", path);
+
+    if let Err(e) = store.apply_retention(opts.keep_sessions) {
+        eprintln!("warning: cannot apply retention: {}", e);
+    }
+    Ok(())
+}
+
+/// Scans `pid` on the `--rounds`/`--interval` schedule, optionally serving the control socket.
+fn run_dump(pid: libc::pid_t, reader: Box<dyn MemoryReader + Send>, opts: &Options) -> Result<(), String> {
+    let package = process_name(pid);
+    let (store, dump_session, manifest) = open_session(&package, pid, opts)?;
+    let schedule = Schedule::from(&manifest.config);
+    let mut scan_session = ScanSession::new(reader, pid, Box::new(dump_session), None, manifest);
+    scan_session.options = scan_options(opts, process_width(pid));
+    let scan_session = Arc::new(Mutex::new(scan_session));
+
+    let name = control::socket_name(&package, pid);
+    if opts.control {
+        println!("Control socket: @{}", name);
+        let scan_session = scan_session.clone();
+        let name = name.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = control::serve(&name, scan_session) {
+                eprintln!("error: cannot serve control socket @{}: {}", name, e);
+            }
+        });
+    }
+
+    session::run_rounds(&scan_session, &schedule);
+    {
+        let mut scan_session = scan_session.lock().unwrap_or_else(|e| e.into_inner());
+        let scan_session = &mut *scan_session;
+        finish_session(&store, scan_session.sink.as_ref(), &mut scan_session.manifest, opts)?;
+    }
+
+    if opts.control {
+        println!("Rounds done, still serving @{} (Ctrl-C to stop)", name);
+        loop {
+            std::thread::park();
+        }
+    }
+    Ok(())
+}
+
+fn run_ctl(opts: &Options) -> Result<(), String> {
+    let command = match opts.positional.as_slice() {
+        [command] if control::COMMANDS.contains(&command.as_str()) => command,
+        _ => return Err(format!("ctl needs exactly one of {:?}", control::COMMANDS)),
+    };
+    let name = match (&opts.socket, opts.pid) {
+        (Some(socket), _) => socket.clone(),
+        (None, Some(pid)) => {
+            let package = opts.package.clone().unwrap_or_else(|| process_name(pid));
+            control::socket_name(&package, pid)
+        }
+        (None, None) => return Err("ctl needs --pid or --socket".to_string()),
+    };
+
+    let reply = control::request(&name, command).map_err(|e| format!("cannot reach @{}: {}", name, e))?;
+    println!("{}", reply);
+    Ok(())
+}
+
+fn session_config(opts: &Options) -> PackageConfig {
+    PackageConfig {
+        enabled: true,
+        delay_secs: 0,
+        deep_search: opts.deep_search,
+        skip_system_dex: !opts.include_system,
+        pointer_tag_mask: opts.pointer_tag_mask,
+        scan_threads: opts.threads,
+        scan_cpu_percent: opts.cpu_percent,
+        reconstruct_headers: opts.reconstruct,
+        rounds: opts.rounds,
+        round_interval_secs: opts.interval_secs,
+        output_dir: opts.out.clone().unwrap_or_default(),
+        keep_sessions: opts.keep_sessions,
+        ..Default::default()
+    }
+}
+
+/// Scan options for a target whose pointer size is `detected`, unless `--pointer-size` says otherwise.
+fn scan_options(opts: &Options, detected: Option<PointerWidth>) -> ScanOptions {
+    let mut options = session_config(opts).scan_options();
+    options.pointer_width = opts.pointer_width.or(detected).unwrap_or_else(PointerWidth::native);
+    options
+}
+
+/// Pointer size of a live process, from its executable.
+fn process_width(pid: libc::pid_t) -> Option<PointerWidth> {
+    PointerWidth::of_process(pid)
+        .inspect_err(|e| eprintln!("warning: cannot tell the pointer size of {}: {}", pid, e))
+        .ok()
+}
+
+fn run_offline(opts: &Options) -> Result<(), String> {
+    let snapshot = match (&opts.core, &opts.image, &opts.maps) {
+        (Some(core), None, None) => {
+            snapshot::open_core_file(core.as_ref()).map_err(|e| format!("cannot load core {}: {}", core, e))?
+        }
+        (None, Some(image), Some(maps)) => {
+            let layout = if opts.sparse { RawLayout::Sparse } else { RawLayout::Concatenated };
+            snapshot::open_raw_image(image.as_ref(), maps.as_ref(), layout)
+                .map_err(|e| format!("cannot load image {}: {}", image, e))?
+        }
+        _ => return Err("offline needs either --core, or --image together with --maps".to_string()),
+    };
+
+    let source = opts.core.as_ref().or(opts.image.as_ref()).unwrap();
+
+    let (results, stats) = dex_scanner::scan_memory_with_stats(&snapshot.reader, &snapshot.ranges, &scan_options(opts, snapshot.pointer_width));
+    println!("Found {} DEX file(s) in {} mapping(s)", results.len(), snapshot.ranges.len());
+    print_stats(&stats);
+    match &opts.out {
+        Some(_) => {
+            let (store, dump_session, mut manifest) = open_session(source, 0, opts)?;
+            let mut seen = SeenDex::default();
+            session::process_results(&snapshot.reader, &snapshot.ranges, &results, &dump_session, None, &mut manifest, &mut seen);
+            finish_session(&store, &dump_session, &mut manifest, opts)?;
+        }
+        None => results.iter().enumerate().for_each(|(i, dex)| print_result(i, dex)),
+    }
+    Ok(())
+}
+
+fn run(command: &str, opts: &Options) -> Result<(), String> {
+    match command {
+        "offline" => return run_offline(opts),
+        "ctl" => return run_ctl(opts),
+        _ => {}
+    }
+
+    let pid = opts.pid.ok_or("--pid is required")?;
+    let reader = open_reader(pid, opts.use_proc_mem)?;
+    if command == "dump" {
+        return run_dump(pid, reader, opts);
+    }
+    let ranges = dex_scanner::get_sorted_maps(pid).map_err(|e| format!("cannot read maps of {}: {}", pid, e))?;
+
+    match command {
+        "scan" => {
+            let (results, stats) = dex_scanner::scan_memory_with_stats(reader.as_ref(), &ranges, &scan_options(opts, process_width(pid)));
+            println!("Found {} DEX file(s) in pid {}", results.len(), pid);
+            print_stats(&stats);
+            for (i, dex) in results.iter().enumerate() {
+                print_result(i, dex);
+            }
+        }
+        "parse" => {
+            let addr = opts.addr.ok_or("--addr is required")?;
+            let dex = dex_parser::parse_dex_at(reader.as_ref(), addr).map_err(|e| format!("parse failed: {}", e))?;
+            let defined = dex.methods.iter().filter(|m| m.is_defined()).count();
+            let with_code = dex.methods.iter().filter(|m| m.code_item.is_some()).count();
+            let fields: usize = dex.classes.iter().map(|c| c.static_fields.len() + c.instance_fields.len()).sum();
+            println!("DEX at 0x{:x}: {:?}", addr, dex.header);
+            println!("  strings:    {}", dex.strings.len());
+            println!("  types:      {}", dex.type_names.len());
+            println!("  class_defs: {}", dex.class_defs.len());
+            println!("  methods:    {} ({} defined, {} with code)", dex.methods.len(), defined, with_code);
+            println!("  fields:     {} defined", fields);
+            if opts.classes {
+                for class in &dex.classes {
+                    print_class(&dex, class);
+                }
+            }
+            if let Some(map_list) = &dex.map_list {
+                println!("  map_list:   {} items at 0x{:x}", map_list.size, dex.header.map_off);
+                for item in &map_list.list {
+                    let name = dex_parser::dex_structs::map_item_type(item.type_).map_or("?", |(name, _)| name);
+                    println!("    0x{:04x} {:<24} {:>8} at 0x{:x}", item.type_, name, item.size, item.offset);
+                }
+            }
+            for issue in &dex.validation.issues {
+                println!("  {:?} in {}: {}", issue.kind, issue.section, issue.message);
+            }
+        }
+        other => return Err(format!("unknown command '{}'", other)),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(command) = args.first() else {
+        eprint!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    if command == "-h" || command == "--help" {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let opts = match parse_options(&args[1..]) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if opts.verbose && log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Debug);
+    }
+
+    match run(command, &opts) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,291 @@
+// companion.rs
+//
+// The app process streams its dumps to the Zygisk companion, which runs as root outside the
+// app sandbox and writes them under the module dir, where the packer cannot delete them.
+
+use super::dex_scanner::MAX_DEX_SIZE;
+use super::dump_store::{DumpSink, DumpStore, SessionDir, StoredDex};
+use log::{error, info, warn, Log, Metadata, Record};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub const COMPANION_DUMP_ROOT: &str = "/data/adb/modules/zygisk-rust-dex-unpacker/dumps";
+
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+const MAX_BODY_SIZE: usize = MAX_DEX_SIZE + 1024 * 1024;
+// Log lines waiting for the companion; more are dropped rather than blocking the logger
+const LOG_QUEUE_SIZE: usize = 1024;
+const LOG_FILE_NAME: &str = "scanner.log";
+const COMPANION_LOG_TAG: &str = "RustDexUnpackerCompanion";
+
+/*
+  Frame, both directions:
+    u32 LE header length | header, JSON object with a "type" | u64 LE body length | body
+
+  app -> companion                                  companion -> app
+    hello     {package, pid, keep_sessions}           {ok, session, path}
+    dex       {sha1}, body = dex                      {ok, path, seen_in_store, seen_in_session}
+    manifest  body = manifest.json                    {ok, path}
+    log       body = one log line                     -
+  Any reply may instead be {ok: false, error}.
+*/
+
+fn write_frame(w: &mut impl Write, header: &Value, body: &[u8]) -> io::Result<()> {
+    let header = serde_json::to_vec(header).map_err(io::Error::other)?;
+    w.write_all(&(header.len() as u32).to_le_bytes())?;
+    w.write_all(&header)?;
+    w.write_all(&(body.len() as u64).to_le_bytes())?;
+    w.write_all(body)?;
+    w.flush()
+}
+
+/// `None` on a clean end of stream.
+fn read_frame(r: &mut impl Read) -> io::Result<Option<(Value, Vec<u8>)>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let header_len = u32::from_le_bytes(len) as usize;
+    if header_len > MAX_HEADER_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame header of {} bytes", header_len)));
+    }
+    let mut header = vec![0u8; header_len];
+    r.read_exact(&mut header)?;
+    let header: Value = serde_json::from_slice(&header).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut len = [0u8; 8];
+    r.read_exact(&mut len)?;
+    let body_len = u64::from_le_bytes(len) as usize;
+    if body_len > MAX_BODY_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame body of {} bytes", body_len)));
+    }
+    let mut body = vec![0u8; body_len];
+    r.read_exact(&mut body)?;
+    Ok(Some((header, body)))
+}
+
+/// Turns an `{ok: false, error}` reply into an error.
+fn check_reply(reply: Option<(Value, Vec<u8>)>) -> io::Result<Value> {
+    let Some((reply, _)) = reply else {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "companion closed the connection"));
+    };
+    if reply["ok"].as_bool() != Some(true) {
+        let error = reply["error"].as_str().unwrap_or("unknown error").to_string();
+        return Err(io::Error::other(format!("companion: {}", error)));
+    }
+    Ok(reply)
+}
+
+/// App side: a session written by the companion.
+pub struct CompanionSink {
+    stream: Arc<Mutex<UnixStream>>,
+    /// Session directory name, as picked by the companion
+    pub name: String,
+    path: String,
+}
+
+impl CompanionSink {
+    /// Opens a session for `package` in the companion.
+    pub fn open(mut stream: UnixStream, package: &str, pid: libc::pid_t, keep_sessions: usize) -> io::Result<Self> {
+        let hello = json!({ "type": "hello", "package": package, "pid": pid, "keep_sessions": keep_sessions });
+        write_frame(&mut stream, &hello, &[])?;
+        let reply = check_reply(read_frame(&mut stream)?)?;
+
+        Ok(Self {
+            name: reply["session"].as_str().unwrap_or_default().to_string(),
+            path: reply["path"].as_str().unwrap_or_default().to_string(),
+            stream: Arc::new(Mutex::new(stream)),
+        })
+    }
+
+    fn call(&self, header: &Value, body: &[u8]) -> io::Result<Value> {
+        let mut stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+        write_frame(&mut *stream, header, body)?;
+        check_reply(read_frame(&mut *stream)?)
+    }
+
+    /// Sends every later log record to the companion as well, into `scanner.log` of the session.
+    pub fn forward_logs(&self) {
+        let (sender, receiver) = mpsc::sync_channel(LOG_QUEUE_SIZE);
+        let stream = self.stream.clone();
+        std::thread::spawn(move || send_logs(stream, receiver));
+        *LOG_FORWARD.lock().unwrap_or_else(|e| e.into_inner()) = Some(sender);
+    }
+}
+
+impl Drop for CompanionSink {
+    /// The log sender holds the stream too, shut it down so the companion sees the end.
+    fn drop(&mut self) {
+        let stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+impl DumpSink for CompanionSink {
+    fn location(&self) -> &str {
+        &self.path
+    }
+
+    fn store_dex(&self, data: &[u8], sha1: &str) -> io::Result<StoredDex> {
+        let reply = self.call(&json!({ "type": "dex", "sha1": sha1 }), data)?;
+        serde_json::from_value(reply).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn write_manifest(&self, json: &[u8]) -> io::Result<String> {
+        let reply = self.call(&json!({ "type": "manifest" }), json)?;
+        Ok(reply["path"].as_str().unwrap_or_default().to_string())
+    }
+}
+
+fn send_logs(stream: Arc<Mutex<UnixStream>>, receiver: Receiver<String>) {
+    let header = json!({ "type": "log" });
+    for line in receiver {
+        let mut stream = stream.lock().unwrap_or_else(|e| e.into_inner());
+        if write_frame(&mut *stream, &header, line.as_bytes()).is_err() {
+            break;
+        }
+    }
+    // The companion is gone, stop queueing
+    *LOG_FORWARD.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+static LOG_FORWARD: Mutex<Option<SyncSender<String>>> = Mutex::new(None);
+
+/// logcat, plus the companion once `CompanionSink::forward_logs` was called.
+struct ModuleLogger {
+    android: android_logger::AndroidLogger,
+}
+
+impl Log for ModuleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.android.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.android.log(record);
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // try_lock: never block or recurse into the logger from the sender thread
+        if let Ok(forward) = LOG_FORWARD.try_lock()
+            && let Some(sender) = forward.as_ref()
+        {
+            let _ = sender.try_send(format!("[{}] {}", record.level(), record.args()));
+        }
+    }
+
+    fn flush(&self) {
+        self.android.flush();
+    }
+}
+
+/// Installs the module logger; later calls are no-ops.
+pub fn init_logger(tag: &str) {
+    static LOGGER: OnceLock<ModuleLogger> = OnceLock::new();
+    let logger = LOGGER.get_or_init(|| ModuleLogger {
+        android: android_logger::AndroidLogger::new(
+            android_logger::Config::default()
+                .with_max_level(log::LevelFilter::Trace)
+                .with_tag(tag),
+        ),
+    });
+    if log::set_logger(logger).is_ok() {
+        // Raised or lowered per package by `log_level` in the config
+        log::set_max_level(log::LevelFilter::Info);
+    }
+}
+
+/// Companion side: serves one app connection until it closes.
+pub fn handle_connection(mut stream: UnixStream) {
+    init_logger(COMPANION_LOG_TAG);
+    if let Err(e) = serve(&mut stream) {
+        error!("Companion connection failed: {}", e);
+    }
+}
+
+fn serve(stream: &mut UnixStream) -> io::Result<()> {
+    let Some((hello, _)) = read_frame(stream)? else {
+        return Ok(());
+    };
+    let package = hello["package"].as_str().unwrap_or_default();
+    let pid = hello["pid"].as_i64().unwrap_or(0) as libc::pid_t;
+    let keep_sessions = hello["keep_sessions"].as_u64().unwrap_or(1).max(1) as usize;
+
+    if hello["type"] != "hello" || !is_valid_package_name(package) {
+        let reply = json!({ "ok": false, "error": format!("bad hello for package '{}'", package) });
+        return write_frame(stream, &reply, &[]);
+    }
+
+    let store = DumpStore::new(&format!("{}/{}", COMPANION_DUMP_ROOT, package));
+    let session = match store.create_session(pid) {
+        Ok(session) => session,
+        Err(e) => {
+            let reply = json!({ "ok": false, "error": e.to_string() });
+            return write_frame(stream, &reply, &[]);
+        }
+    };
+    info!("Companion writing dumps of {} ({}) to {}", package, pid, session.path);
+    write_frame(stream, &json!({ "ok": true, "session": session.name, "path": session.path }), &[])?;
+
+    let mut log_file = None;
+    while let Some((header, body)) = read_frame(stream)? {
+        let reply = match header["type"].as_str().unwrap_or_default() {
+            "dex" => store_dex(&session, &header, &body),
+            "manifest" => session.write_manifest(&body).map(|path| json!({ "path": path })),
+            "log" => {
+                append_log(&session, &mut log_file, &body);
+                continue;
+            }
+            other => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown frame type '{}'", other))),
+        };
+
+        let reply = match reply {
+            Ok(Value::Object(mut reply)) => {
+                reply.insert("ok".to_string(), Value::Bool(true));
+                Value::Object(reply)
+            }
+            Ok(_) => json!({ "ok": true }),
+            Err(e) => json!({ "ok": false, "error": e.to_string() }),
+        };
+        write_frame(stream, &reply, &[])?;
+    }
+
+    info!("Companion session {} of {} closed", session.name, package);
+    store.apply_retention(keep_sessions)
+}
+
+fn store_dex(session: &SessionDir, header: &Value, data: &[u8]) -> io::Result<Value> {
+    // Never trust the name the app sends, it becomes a path
+    let sha1: String = Sha1::digest(data).iter().map(|b| format!("{:02x}", b)).collect();
+    if header["sha1"].as_str() != Some(sha1.as_str()) {
+        warn!("Companion got a dex whose SHA-1 does not match its header, storing as {}", sha1);
+    }
+    let stored = session.store_dex(data, &sha1)?;
+    serde_json::to_value(stored).map_err(io::Error::other)
+}
+
+fn append_log(session: &SessionDir, log_file: &mut Option<std::fs::File>, line: &[u8]) {
+    if log_file.is_none() {
+        let path = format!("{}/{}", session.path, LOG_FILE_NAME);
+        *log_file = std::fs::OpenOptions::new().create(true).append(true).open(path).ok();
+    }
+    if let Some(file) = log_file {
+        let _ = file.write_all(line);
+        let _ = file.write_all(b"\n");
+    }
+}
+
+/// Package or process name, used as a directory name.
+fn is_valid_package_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 255
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | ':'))
+}
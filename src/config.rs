@@ -0,0 +1,412 @@
+// config.rs
+
+use super::companion::COMPANION_DUMP_ROOT;
+use super::dex_scanner::{PointerWidth, ScanOptions, DEFAULT_POINTER_TAG_MASK, MAX_DEX_SIZE};
+use super::dump_store::DEFAULT_KEEP_SESSIONS;
+use super::targets::{ProcessInfo, TargetRule};
+use log::{error, info, warn, LevelFilter};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+pub const CONFIG_TOML: &str = "config.toml";
+pub const CONFIG_JSON: &str = "config.json";
+
+// Pre-config files, still honoured when neither config file exists
+const LEGACY_WHITELIST: &str = "whitelist.txt";
+const LEGACY_FORCE_RESOLVE: &str = "force_resolve.txt";
+
+/// `{package}` is replaced by the package name.
+pub const DEFAULT_OUTPUT_DIR: &str = "/data/data/{package}/files/rust_dumps";
+/// Process name of system_server, also its directory name under the companion dump root.
+pub const SYSTEM_SERVER: &str = "system_server";
+const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+const MIN_DUMP_SIZE: usize = 0x70;
+const MAX_SCAN_THREADS: usize = 64;
+const MAX_ROUNDS: u32 = 1000;
+const MAX_BACKOFF: f64 = 16.0;
+
+/// Module configuration, `config.toml` (or `config.json`) in the module dir:
+/// ```toml
+/// [defaults]
+/// delay_secs = 10
+/// deep_search = true
+///
+/// [packages."com.example.app"]
+/// enabled = true
+/// force_resolve = true
+///
+/// [[targets]]
+/// process = "com.example.*"
+///
+/// [system_server]
+/// enabled = true
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ModuleConfig {
+    #[serde(default)]
+    pub defaults: Overrides,
+    /// Keyed by package (every process of it) or by process name (`com.foo:push`)
+    #[serde(default)]
+    pub packages: HashMap<String, Overrides>,
+    /// Enable or exclude processes by pattern, see `TargetRule`
+    #[serde(default)]
+    pub targets: Vec<TargetRule>,
+    /// Settings for system_server, only scanned when enabled here
+    #[serde(default)]
+    pub system_server: Overrides,
+}
+
+/// One `[defaults]` or `[packages."<name>"]` table; unset keys fall through to
+/// `[defaults]`, then to the built-in defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Overrides {
+    pub enabled: Option<bool>,
+    pub delay_secs: Option<u64>,
+    pub magic_scan: Option<bool>,
+    pub deep_search: Option<bool>,
+    pub force_resolve: Option<bool>,
+    pub skip_prefixes: Option<Vec<String>>,
+    pub output_dir: Option<String>,
+    pub max_dump_size: Option<usize>,
+    pub rounds: Option<u32>,
+    pub round_interval_secs: Option<u64>,
+    pub backoff: Option<f64>,
+    pub max_duration_secs: Option<u64>,
+    pub keep_sessions: Option<usize>,
+    pub control_socket: Option<bool>,
+    pub companion: Option<bool>,
+    pub skip_boot_classpath: Option<bool>,
+    pub skip_system_dex: Option<bool>,
+    /// A string, TOML integers cannot hold the top bit: `"0xff00000000000000"`
+    pub pointer_tag_mask: Option<String>,
+    pub scan_threads: Option<usize>,
+    pub scan_cpu_percent: Option<u32>,
+    pub reconstruct_headers: Option<bool>,
+    pub log_level: Option<String>,
+}
+
+/// Effective settings for one package, recorded in the session manifest.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageConfig {
+    pub enabled: bool,
+    pub delay_secs: u64,
+    pub magic_scan: bool,
+    pub deep_search: bool,
+    pub force_resolve: bool,
+    pub skip_prefixes: Vec<String>,
+    pub output_dir: String,
+    pub max_dump_size: usize,
+    /// Scan rounds per launch, the first after `delay_secs`
+    pub rounds: u32,
+    /// Wait between the first and second round
+    pub round_interval_secs: u64,
+    /// Each later wait is the previous one times this
+    pub backoff: f64,
+    /// No round starts later than this after launch, 0 for no limit
+    pub max_duration_secs: u64,
+    /// Dump sessions kept under `output_dir`
+    pub keep_sessions: usize,
+    /// Accept `scan`/`dump`/`resolve`/`status`/`list` on an abstract Unix socket
+    pub control_socket: bool,
+    /// Stream dumps to the root companion instead of writing them inside the app
+    pub companion: bool,
+    /// Ignore dex of the boot classpath and boot image, on by default for system_server
+    pub skip_boot_classpath: bool,
+    /// Ignore dex classified as framework or apex, off by default for system_server
+    pub skip_system_dex: bool,
+    /// Tag bits the pointer scan clears before the lookup, 0 to take words as they are
+    pub pointer_tag_mask: u64,
+    /// Scan worker threads, 0 for half the CPUs
+    pub scan_threads: usize,
+    /// Share of one CPU each scan worker may use, lower keeps the app responsive
+    pub scan_cpu_percent: u32,
+    /// Rebuild wiped headers from the map_list or the id tables
+    pub reconstruct_headers: bool,
+    pub log_level: String,
+}
+
+impl Default for PackageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            delay_secs: 10,
+            magic_scan: true,
+            deep_search: true,
+            force_resolve: false,
+            skip_prefixes: Vec::new(),
+            output_dir: DEFAULT_OUTPUT_DIR.to_string(),
+            max_dump_size: MAX_DEX_SIZE,
+            rounds: 1,
+            round_interval_secs: 30,
+            backoff: 1.0,
+            max_duration_secs: 0,
+            keep_sessions: DEFAULT_KEEP_SESSIONS,
+            control_socket: false,
+            companion: true,
+            skip_boot_classpath: false,
+            skip_system_dex: true,
+            pointer_tag_mask: DEFAULT_POINTER_TAG_MASK,
+            scan_threads: 0,
+            scan_cpu_percent: 100,
+            reconstruct_headers: false,
+            log_level: "info".to_string(),
+        }
+    }
+}
+
+impl PackageConfig {
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            magic_scan: self.magic_scan,
+            deep_search: self.deep_search,
+            skip_prefixes: self.skip_prefixes.clone(),
+            max_dex_size: self.max_dump_size,
+            skip_boot_classpath: self.skip_boot_classpath,
+            skip_system_dex: self.skip_system_dex,
+            pointer_tag_mask: self.pointer_tag_mask,
+            pointer_width: PointerWidth::native(),
+            threads: self.scan_threads,
+            cpu_percent: self.scan_cpu_percent,
+            reconstruct_headers: self.reconstruct_headers,
+        }
+    }
+
+    pub fn level_filter(&self) -> LevelFilter {
+        self.log_level.parse().unwrap_or(LevelFilter::Info)
+    }
+
+    /// Applies the keys set in `overrides`; invalid values were already reported by `validate`
+    /// and are clamped or ignored here.
+    fn apply(&mut self, overrides: &Overrides) {
+        if let Some(v) = overrides.enabled { self.enabled = v; }
+        if let Some(v) = overrides.delay_secs { self.delay_secs = v; }
+        if let Some(v) = overrides.magic_scan { self.magic_scan = v; }
+        if let Some(v) = overrides.deep_search { self.deep_search = v; }
+        if let Some(v) = overrides.force_resolve { self.force_resolve = v; }
+        if let Some(v) = &overrides.skip_prefixes { self.skip_prefixes = v.clone(); }
+        if let Some(v) = &overrides.output_dir && !v.is_empty() { self.output_dir = v.clone(); }
+        if let Some(v) = overrides.max_dump_size { self.max_dump_size = v.clamp(MIN_DUMP_SIZE, MAX_DEX_SIZE); }
+        if let Some(v) = overrides.rounds { self.rounds = v.clamp(1, MAX_ROUNDS); }
+        if let Some(v) = overrides.round_interval_secs { self.round_interval_secs = v; }
+        if let Some(v) = overrides.backoff && v.is_finite() && v > 0.0 { self.backoff = v.min(MAX_BACKOFF); }
+        if let Some(v) = overrides.max_duration_secs { self.max_duration_secs = v; }
+        if let Some(v) = overrides.keep_sessions { self.keep_sessions = v.max(1); }
+        if let Some(v) = overrides.control_socket { self.control_socket = v; }
+        if let Some(v) = overrides.companion { self.companion = v; }
+        if let Some(v) = overrides.skip_boot_classpath { self.skip_boot_classpath = v; }
+        if let Some(v) = overrides.skip_system_dex { self.skip_system_dex = v; }
+        if let Some(v) = overrides.pointer_tag_mask.as_deref().and_then(parse_mask) { self.pointer_tag_mask = v; }
+        if let Some(v) = overrides.scan_threads { self.scan_threads = v.min(MAX_SCAN_THREADS); }
+        if let Some(v) = overrides.scan_cpu_percent { self.scan_cpu_percent = v.clamp(1, 100); }
+        if let Some(v) = overrides.reconstruct_headers { self.reconstruct_headers = v; }
+        if let Some(v) = &overrides.log_level && LOG_LEVELS.contains(&v.to_ascii_lowercase().as_str()) {
+            self.log_level = v.to_ascii_lowercase();
+        }
+    }
+}
+
+impl ModuleConfig {
+    /// Parses a TOML config. Unknown keys and invalid values are returned as warnings.
+    pub fn from_toml(text: &str) -> Result<(Self, Vec<String>), String> {
+        let mut warnings = Vec::new();
+        let de = toml::Deserializer::new(text);
+        let config: Self = serde_ignored::deserialize(de, |path| warnings.push(format!("unknown key '{}'", path)))
+            .map_err(|e| e.to_string())?;
+        config.validate(&mut warnings);
+        Ok((config, warnings))
+    }
+
+    /// Parses a JSON config with the same layout as the TOML one.
+    pub fn from_json(text: &str) -> Result<(Self, Vec<String>), String> {
+        let mut warnings = Vec::new();
+        let mut de = serde_json::Deserializer::from_str(text);
+        let config: Self = serde_ignored::deserialize(&mut de, |path| warnings.push(format!("unknown key '{}'", path)))
+            .map_err(|e| e.to_string())?;
+        de.end().map_err(|e| e.to_string())?;
+        config.validate(&mut warnings);
+        Ok((config, warnings))
+    }
+
+    /// Only the packages in `whitelist.txt` are enabled, `force_resolve.txt` turns resolution on for all.
+    pub fn from_legacy(whitelist: &[String], force_resolve: bool) -> Self {
+        let mut config = Self::default();
+        config.defaults.force_resolve = Some(force_resolve);
+        for package in whitelist {
+            let overrides = Overrides { enabled: Some(true), ..Default::default() };
+            config.packages.insert(package.clone(), overrides);
+        }
+        config
+    }
+
+    /// Reads the config from the module dir, falling back to the legacy files.
+    /// Problems are logged; a config that fails to parse enables nothing.
+    pub fn load(dir: &Path) -> Self {
+        let loaded = match std::fs::read_to_string(dir.join(CONFIG_TOML)) {
+            Ok(text) => Some((CONFIG_TOML, Self::from_toml(&text))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => match std::fs::read_to_string(dir.join(CONFIG_JSON)) {
+                Ok(text) => Some((CONFIG_JSON, Self::from_json(&text))),
+                Err(_) => None,
+            },
+            Err(e) => Some((CONFIG_TOML, Err(e.to_string()))),
+        };
+
+        match loaded {
+            Some((name, Ok((config, warnings)))) => {
+                for warning in &warnings {
+                    warn!("{}: {}", name, warning);
+                }
+                config
+            }
+            Some((name, Err(e))) => {
+                error!("Failed to load {}, no package is enabled: {}", name, e);
+                Self::default()
+            }
+            None => {
+                let whitelist = read_whitelist(&dir.join(LEGACY_WHITELIST));
+                let force_resolve = dir.join(LEGACY_FORCE_RESOLVE).exists();
+                if !whitelist.is_empty() {
+                    info!("No {} found, using {} ({} packages)", CONFIG_TOML, LEGACY_WHITELIST, whitelist.len());
+                }
+                Self::from_legacy(&whitelist, force_resolve)
+            }
+        }
+    }
+
+    /// Settings for `process`: built-in defaults, then `[defaults]`, then the table of its
+    /// package, then the table of the process itself. Also returns what decided `enabled`:
+    /// an exclude rule, else an explicit `enabled` in one of those tables, else the first
+    /// matching include rule, else `[defaults]`.
+    pub fn for_process(&self, process: &ProcessInfo) -> (PackageConfig, String) {
+        let mut config = PackageConfig::default();
+        config.apply(&self.defaults);
+        let mut decided_by = "defaults".to_string();
+
+        let package = process.package.as_deref().unwrap_or(&process.process);
+        let mut tables = vec![package];
+        if process.process != package {
+            tables.push(&process.process);
+        }
+        for name in tables {
+            if let Some(overrides) = self.packages.get(name) {
+                config.apply(overrides);
+                if overrides.enabled.is_some() {
+                    decided_by = format!("packages.\"{}\"", name);
+                }
+            }
+        }
+        config.output_dir = config.output_dir.replace("{package}", package);
+
+        let matching = || self.targets.iter().enumerate().filter(|(_, rule)| rule.matches(process));
+        if let Some((i, rule)) = matching().find(|(_, rule)| rule.exclude) {
+            config.enabled = false;
+            decided_by = format!("{} (exclude)", rule.describe(i));
+        } else if decided_by == "defaults"
+            && let Some((i, rule)) = matching().next()
+        {
+            config.enabled = true;
+            decided_by = rule.describe(i);
+        }
+        (config, decided_by)
+    }
+
+    /// Like `for_process`, but only `[system_server]` can enable it or move its output, which
+    /// defaults to the companion's root-owned dump dir: system_server has no app data dir and
+    /// only dumps through the companion.
+    pub fn for_system_server(&self) -> PackageConfig {
+        // Services and OEM plugins live in framework dirs too, only the boot classpath is noise
+        let mut config = PackageConfig { skip_boot_classpath: true, skip_system_dex: false, ..Default::default() };
+        config.apply(&self.defaults);
+        config.enabled = false;
+        config.output_dir = format!("{}/{}", COMPANION_DUMP_ROOT, SYSTEM_SERVER);
+        config.apply(&self.system_server);
+        config.output_dir = config.output_dir.replace("{package}", SYSTEM_SERVER);
+        config
+    }
+
+    fn validate(&self, warnings: &mut Vec<String>) {
+        let tables = std::iter::once(("defaults".to_string(), &self.defaults))
+            .chain(std::iter::once((SYSTEM_SERVER.to_string(), &self.system_server)))
+            .chain(self.packages.iter().map(|(name, o)| (format!("packages.{}", name), o)));
+        for (table, overrides) in tables {
+            if let Some(rounds) = overrides.rounds
+                && !(1..=MAX_ROUNDS).contains(&rounds)
+            {
+                warnings.push(format!("{}.rounds {} out of range 1..={}, clamped", table, rounds, MAX_ROUNDS));
+            }
+            if let Some(backoff) = overrides.backoff {
+                if !(backoff.is_finite() && backoff > 0.0) {
+                    warnings.push(format!("{}.backoff must be a positive number, ignored", table));
+                } else if backoff > MAX_BACKOFF {
+                    warnings.push(format!("{}.backoff is more than {}, clamped", table, MAX_BACKOFF));
+                }
+            }
+            if overrides.keep_sessions == Some(0) {
+                warnings.push(format!("{}.keep_sessions must be at least 1, using 1", table));
+            }
+            if let Some(size) = overrides.max_dump_size
+                && !(MIN_DUMP_SIZE..=MAX_DEX_SIZE).contains(&size)
+            {
+                warnings.push(format!("{}.max_dump_size {} out of range 0x{:x}..=0x{:x}, clamped", table, size, MIN_DUMP_SIZE, MAX_DEX_SIZE));
+            }
+            if let Some(level) = &overrides.log_level
+                && !LOG_LEVELS.contains(&level.to_ascii_lowercase().as_str())
+            {
+                warnings.push(format!("{}.log_level '{}' is not one of {:?}, ignored", table, level, LOG_LEVELS));
+            }
+            if overrides.scan_threads.is_some_and(|n| n > MAX_SCAN_THREADS) {
+                warnings.push(format!("{}.scan_threads is more than {}, clamped", table, MAX_SCAN_THREADS));
+            }
+            if let Some(percent) = overrides.scan_cpu_percent
+                && !(1..=100).contains(&percent)
+            {
+                warnings.push(format!("{}.scan_cpu_percent {} out of range 1..=100, clamped", table, percent));
+            }
+            if let Some(mask) = &overrides.pointer_tag_mask
+                && parse_mask(mask).is_none()
+            {
+                warnings.push(format!("{}.pointer_tag_mask '{}' is not a hex or decimal number, ignored", table, mask));
+            }
+            if overrides.output_dir.as_ref().is_some_and(|dir| dir.is_empty()) {
+                warnings.push(format!("{}.output_dir is empty, ignored", table));
+            }
+        }
+
+        if self.system_server.enabled == Some(true) && self.system_server.companion.or(self.defaults.companion) == Some(false) {
+            warnings.push(format!("{}.companion = false, but {} can only dump through the companion", SYSTEM_SERVER, SYSTEM_SERVER));
+        }
+
+        for (i, rule) in self.targets.iter().enumerate() {
+            if !rule.has_conditions() {
+                warnings.push(format!("{} has no condition and matches nothing", rule.describe(i)));
+            }
+            if let Some(regex) = &rule.process_regex
+                && let Err(e) = &regex.compiled
+            {
+                warnings.push(format!("{}.process_regex is invalid and matches nothing: {}", rule.describe(i), e));
+            }
+        }
+    }
+}
+
+/// `0x`-prefixed hex or decimal.
+fn parse_mask(text: &str) -> Option<u64> {
+    let text = text.trim().replace('_', "");
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn read_whitelist(path: &Path) -> Vec<String> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
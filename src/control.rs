@@ -0,0 +1,161 @@
+// control.rs
+
+use super::session::ScanSession;
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(target_os = "android")]
+use std::os::android::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+
+pub const COMMANDS: [&str; 5] = ["scan", "dump", "resolve", "status", "list"];
+
+// A client that sends nothing is dropped after this, so it cannot block the socket
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+const AID_SHELL: libc::uid_t = 2000;
+
+/// Abstract socket name (no leading NUL) of the scanner of `package` running as `pid`.
+pub fn socket_name(package: &str, pid: libc::pid_t) -> String {
+    format!("dexunpack.{}.{}", package, pid)
+}
+
+/// Serves the control socket forever, one client at a time.
+///
+/// The protocol is one command per line (`scan`, `dump`, `resolve`, `status` or `list`),
+/// answered by one line of JSON with `"ok": true|false`. Only root, shell and the
+/// process' own uid may connect.
+pub fn serve(name: &str, session: Arc<Mutex<ScanSession>>) -> io::Result<()> {
+    let addr = SocketAddr::from_abstract_name(name.as_bytes())?;
+    let listener = UnixListener::bind_addr(&addr)?;
+    info!("Control socket listening on @{}", name);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Control socket accept failed: {}", e);
+                continue;
+            }
+        };
+
+        match peer_uid(&stream) {
+            Some(uid) if uid == 0 || uid == AID_SHELL || uid == unsafe { libc::getuid() } => {}
+            uid => {
+                warn!("Control socket rejected client with uid {:?}", uid);
+                continue;
+            }
+        }
+
+        if let Err(e) = handle_client(stream, &session) {
+            warn!("Control socket client error: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Sends one command to the socket `name` and returns the JSON reply line.
+pub fn request(name: &str, command: &str) -> io::Result<String> {
+    let addr = SocketAddr::from_abstract_name(name.as_bytes())?;
+    let mut stream = UnixStream::connect_addr(&addr)?;
+    writeln!(stream, "{}", command)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(reply.trim_end().to_string())
+}
+
+fn handle_client(stream: UnixStream, session: &Mutex<ScanSession>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let command = line?;
+        let command = command.trim();
+        if command.is_empty() {
+            continue;
+        }
+
+        info!("Control command: {}", command);
+        let reply = handle_command(command, session);
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+fn handle_command(command: &str, session: &Mutex<ScanSession>) -> Value {
+    // A scheduled round holds the lock while it runs, commands wait for it
+    let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+
+    let result = match command {
+        "scan" => session.scan().map(|(results, stats)| {
+            let dex: Vec<Value> = results
+                .iter()
+                .map(|r| {
+                    json!({
+                        "address": format!("0x{:x}", r.addr),
+                        "size": r.size,
+                        "version": r.version,
+                        "source": r.source,
+                        "classification": r.classification,
+                        "map": r.map,
+                        "referenced_from": r.referenced_from,
+                        "reconstruction": r.reconstruction,
+                    })
+                })
+                .collect();
+            json!({ "candidates": dex, "stats": stats })
+        }),
+        "dump" => session.dump_round().map(|round| json!({ "round": round })),
+        "resolve" => session.resolve_round().map(|round| json!({ "round": round })),
+        "status" => Ok(json!({
+            "package": session.manifest.package,
+            "pid": session.pid,
+            "session": session.sink.location(),
+            "rounds": session.manifest.rounds.len(),
+            "dex_files": session.manifest.dex_files.len(),
+        })),
+        "list" => {
+            let dex: Vec<Value> = session
+                .manifest
+                .dex_files
+                .iter()
+                .map(|e| json!({ "index": e.index, "round": e.round, "address": e.address, "size": e.size, "file": e.file, "sha1": e.sha1 }))
+                .collect();
+            Ok(json!({ "dex_files": dex }))
+        }
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown command '{}', expected one of {:?}", other, COMMANDS),
+        )),
+    };
+
+    match result {
+        Ok(Value::Object(mut body)) => {
+            body.insert("ok".to_string(), Value::Bool(true));
+            Value::Object(body)
+        }
+        Ok(body) => json!({ "ok": true, "result": body }),
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    }
+}
+
+fn peer_uid(stream: &UnixStream) -> Option<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (ret == 0).then_some(cred.uid)
+}
@@ -0,0 +1,165 @@
+// dex_dumper.rs
+
+use super::dex_reconstruct::Reconstruction;
+use super::dex_repair::{self, RepairReport};
+use super::dex_scanner::find_map_binary;
+use super::dump_store::DumpSink;
+use super::maps::MapRange;
+use super::memory_reader::MemoryReader;
+use log::{info, warn};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::io;
+
+/// A run of pages inside a dump that could not be read and was zero-filled.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MemoryHole {
+    /// Offset from the start of the dex
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Everything that happened while copying one dex out of memory.
+#[derive(Debug, Clone)]
+pub struct DumpReport {
+    pub path: String,
+    /// `file_size` as claimed by the header
+    pub requested_size: usize,
+    /// Bytes actually written, after capping at the end of the mapping chain
+    pub dumped_size: usize,
+    pub holes: Vec<MemoryHole>,
+    pub repair: RepairReport,
+    /// SHA-1 of the file as written, hex encoded; also its file name
+    pub sha1: String,
+    /// Identical content was already dumped by an earlier session
+    pub seen_in_store: bool,
+    /// Identical content was already dumped earlier in this session
+    pub seen_in_session: bool,
+}
+
+impl DumpReport {
+    pub fn hole_bytes(&self) -> usize {
+        self.holes.iter().map(|h| h.size).sum()
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.dumped_size < self.requested_size
+    }
+}
+
+pub fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        n if n > 0 => n as usize,
+        _ => 4096,
+    }
+}
+
+/// Returns how many bytes starting at `addr` are covered by back-to-back mappings.
+/// A forged `file_size` can never make us read past the last one.
+pub fn mapped_extent(ranges: &[MapRange], addr: usize) -> usize {
+    let Some(first) = find_map_binary(ranges, addr) else {
+        return 0;
+    };
+
+    let mut end = first.start() + first.size();
+    for range in ranges.iter().filter(|r| r.start() > first.start()) {
+        if range.start() != end {
+            break;
+        }
+        end = range.start() + range.size();
+    }
+    end - addr
+}
+
+/// Copies `size` bytes at `addr` one page at a time.
+/// Unreadable pages are zero-filled and reported as holes instead of faulting.
+pub fn read_dex_image(
+    reader: &dyn MemoryReader,
+    addr: usize,
+    size: usize,
+    ranges: &[MapRange],
+) -> (Vec<u8>, Vec<MemoryHole>) {
+    let size = std::cmp::min(size, mapped_extent(ranges, addr));
+    let page = page_size();
+
+    let mut data = vec![0u8; size];
+    let mut holes: Vec<MemoryHole> = Vec::new();
+    let mut offset = 0;
+
+    while offset < size {
+        // Align every read after the first one to a page boundary
+        let cur_addr = addr + offset;
+        let to_read = std::cmp::min(page - (cur_addr % page), size - offset);
+
+        let n = reader.read_memory(cur_addr, &mut data[offset..offset + to_read]).unwrap_or(0);
+
+        if n < to_read {
+            let hole_off = offset + n;
+            let hole_size = to_read - n;
+            data[hole_off..offset + to_read].fill(0);
+            match holes.last_mut() {
+                Some(last) if last.offset + last.size == hole_off => last.size += hole_size,
+                _ => holes.push(MemoryHole { offset: hole_off, size: hole_size }),
+            }
+        }
+        offset += to_read;
+    }
+
+    (data, holes)
+}
+
+/// Reads, repairs and saves a single dex into `session`, named by its SHA-1.
+/// A `reconstruction` of a wiped header is written over the header first.
+pub fn dump_dex_to_file(
+    reader: &dyn MemoryReader,
+    session: &dyn DumpSink,
+    index: usize,
+    addr: usize,
+    size: usize,
+    ranges: &[MapRange],
+    reconstruction: Option<&Reconstruction>,
+) -> io::Result<DumpReport> {
+    let (mut data, holes) = read_dex_image(reader, addr, size, ranges);
+    if data.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("0x{:x} is not mapped", addr)));
+    }
+
+    if let Some(reconstruction) = reconstruction {
+        reconstruction.apply(&mut data);
+        for field in &reconstruction.inferred_fields {
+            info!("Inferred DEX #{} header {}: {} -> {}", index, field.field, field.old_value, field.new_value);
+        }
+    }
+
+    let repair = dex_repair::repair_header(&mut data);
+    for fix in &repair.fixes {
+        info!("Repaired DEX #{} header {}: {} -> {}", index, fix.field, fix.old_value, fix.new_value);
+    }
+
+    let sha1: String = Sha1::digest(&data).iter().map(|b| format!("{:02x}", b)).collect();
+    let stored = session.store_dex(&data, &sha1)?;
+
+    let report = DumpReport {
+        path: stored.path,
+        requested_size: size,
+        dumped_size: data.len(),
+        holes,
+        repair,
+        sha1,
+        seen_in_store: stored.seen_in_store,
+        seen_in_session: stored.seen_in_session,
+    };
+
+    if report.is_truncated() {
+        warn!("DEX #{} truncated to 0x{:x} of 0x{:x} bytes (end of mapping)", index, report.dumped_size, report.requested_size);
+    }
+    if !report.holes.is_empty() {
+        warn!("DEX #{} has {} unreadable hole(s), 0x{:x} bytes zero-filled", index, report.holes.len(), report.hole_bytes());
+    }
+    if report.seen_in_session || report.seen_in_store {
+        info!("Dex identical to an earlier dump, kept as {}", report.path);
+    } else {
+        info!("Dex saved to {} (version {})", report.path, report.repair.version);
+    }
+    Ok(report)
+}
@@ -1,8 +1,10 @@
 // dex_parser.rs
 
 use bytemuck;
+use serde::Serialize;
 use std::collections::HashMap;
-use super::dex_scanner::safe_read_memory;
+use super::dex_scanner::MAX_DEX_SIZE;
+use super::memory_reader::MemoryReader;
 use std::io::{Error, ErrorKind};
 use std::mem::size_of;
 
@@ -31,8 +33,85 @@ pub struct ParsedDexFile {
     /// Map of `type_id_index` -> `String` (e.g., "Ljava/lang/String;")
     pub type_names: HashMap<u32, String>,
     
-    /// Fully parsed method information
+    /// Every `method_id`, defined here or only referenced
     pub methods: Vec<ParsedMethod>,
+
+    /// Classes defined in this dex, in `class_defs` order
+    pub classes: Vec<ParsedClass>,
+
+    /// The map_list at `header.map_off`, if it could be read
+    pub map_list: Option<dex_structs::MapList>,
+
+    /// Where the header, the map_list and the file size disagree
+    pub validation: ValidationReport,
+}
+
+impl ParsedDexFile {
+    /// CodeItem of the method with this `method_idx`, `None` for abstract, native and
+    /// only referenced methods.
+    pub fn code_item(&self, method_idx: u32) -> Option<&dex_structs::CodeItem> {
+        self.methods.get(method_idx as usize)?.code_item.as_ref()
+    }
+}
+
+/// What is wrong with a section of a dex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// `file_size` claims more than could be read
+    Truncated,
+    /// `map_off` is zero or outside the file, or the list does not fit
+    MissingMapList,
+    /// A map item type that is not part of the dex format
+    UnknownType,
+    /// A map item type listed twice
+    DuplicateType,
+    /// Map items not in ascending offset order
+    Unsorted,
+    /// A section in the header but not in the map_list, or the map_list lacks itself
+    MissingSection,
+    /// Header and map_list disagree on the item count of a section
+    CountMismatch,
+    /// Header and map_list disagree on where a section starts
+    OffsetMismatch,
+    /// A section runs into the next one
+    Overlap,
+    /// A section starts before the end of the header or ends past `file_size`
+    OutOfRange,
+}
+
+/// One inconsistency of a parsed dex.
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    pub kind: IssueKind,
+    /// e.g. `string_ids`, `map_list`
+    pub section: String,
+    pub message: String,
+}
+
+/// Every inconsistency found while parsing. Forged header fields and sections relocated
+/// behind the header's back show up here; parsing itself still trusts the header.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn push(&mut self, kind: IssueKind, section: &str, message: String) {
+        self.issues.push(ValidationIssue { kind, section: section.to_string(), message });
+    }
+}
+
+/// Why a dex could not be parsed, with what validation found before the parser gave up.
+#[derive(Debug)]
+pub struct ParseFailure {
+    pub error: Error,
+    /// `None` when not even the header could be read
+    pub validation: Option<ValidationReport>,
 }
 
 /// Represents a single, fully resolved method.
@@ -48,6 +127,59 @@ pub struct ParsedMethod {
     
     /// Parsed code item, if the method is not abstract/native
     pub code_item: Option<dex_structs::CodeItem>,
+
+    /// From the class_data of its class, `None` for a method this dex only references
+    pub access_flags: Option<u32>,
+}
+
+impl ParsedMethod {
+    /// Whether a class of this dex defines the method, abstract and native ones included.
+    pub fn is_defined(&self) -> bool {
+        self.access_flags.is_some()
+    }
+}
+
+/// A class defined in the dex, from its `class_def_item` and `class_data_item`.
+#[derive(Debug, Clone)]
+pub struct ParsedClass {
+    pub class_idx: u32,
+    /// e.g. "Lcom/example/Foo;"
+    pub descriptor: String,
+    pub access_flags: u32,
+    /// `None` for `java.lang.Object`
+    pub superclass: Option<String>,
+    pub interfaces: Vec<String>,
+    pub source_file: Option<String>,
+    pub static_fields: Vec<ParsedField>,
+    pub instance_fields: Vec<ParsedField>,
+    pub direct_methods: Vec<ParsedClassMethod>,
+    pub virtual_methods: Vec<ParsedClassMethod>,
+    /// Why the `class_data_item` could not be read to its end, the members above are the ones
+    /// decoded before the error
+    pub class_data_error: Option<String>,
+}
+
+/// A field defined by a class.
+#[derive(Debug, Clone)]
+pub struct ParsedField {
+    pub field_idx: u32,
+    pub name: String,
+    /// Type descriptor, e.g. "I" or "Ljava/lang/String;"
+    pub type_name: String,
+    pub access_flags: u32,
+}
+
+/// A method defined by a class, its CodeItem is on `ParsedDexFile::methods[method_idx]`.
+#[derive(Debug, Clone)]
+pub struct ParsedClassMethod {
+    pub method_idx: u32,
+    pub name: String,
+    /// e.g. "(Ljava/lang/String;)V"
+    pub signature: String,
+    pub access_flags: u32,
+    /// Why the method has a `code_off` but no `code_item`, or a `code_item` without its
+    /// try blocks and handlers
+    pub code_error: Option<String>,
 }
 
 // --- AOSP Struct Definitions ---
@@ -108,13 +240,54 @@ pub mod dex_structs {
         pub offset: u32,
     }
 
-    #[allow(dead_code)]
     #[derive(Debug, Clone)]
     pub struct MapList {
         pub size: u32,
         pub list: Vec<MapItem>,
     }
 
+    // --- Map item types ---
+    pub const TYPE_HEADER_ITEM: u16 = 0x0000;
+    pub const TYPE_STRING_ID_ITEM: u16 = 0x0001;
+    pub const TYPE_TYPE_ID_ITEM: u16 = 0x0002;
+    pub const TYPE_PROTO_ID_ITEM: u16 = 0x0003;
+    pub const TYPE_FIELD_ID_ITEM: u16 = 0x0004;
+    pub const TYPE_METHOD_ID_ITEM: u16 = 0x0005;
+    pub const TYPE_CLASS_DEF_ITEM: u16 = 0x0006;
+    pub const TYPE_CALL_SITE_ID_ITEM: u16 = 0x0007;
+    pub const TYPE_METHOD_HANDLE_ITEM: u16 = 0x0008;
+    pub const TYPE_MAP_LIST: u16 = 0x1000;
+
+    /// Every map item type of the dex format: code, section name, size of one item if fixed.
+    pub const MAP_ITEM_TYPES: [(u16, &str, Option<usize>); 21] = [
+        (TYPE_HEADER_ITEM, "header", None),
+        (TYPE_STRING_ID_ITEM, "string_ids", Some(4)),
+        (TYPE_TYPE_ID_ITEM, "type_ids", Some(4)),
+        (TYPE_PROTO_ID_ITEM, "proto_ids", Some(12)),
+        (TYPE_FIELD_ID_ITEM, "field_ids", Some(8)),
+        (TYPE_METHOD_ID_ITEM, "method_ids", Some(8)),
+        (TYPE_CLASS_DEF_ITEM, "class_defs", Some(32)),
+        (TYPE_CALL_SITE_ID_ITEM, "call_site_ids", Some(4)),
+        (TYPE_METHOD_HANDLE_ITEM, "method_handles", Some(8)),
+        (TYPE_MAP_LIST, "map_list", None),
+        (0x1001, "type_lists", None),
+        (0x1002, "annotation_set_ref_lists", None),
+        (0x1003, "annotation_sets", None),
+        (0x2000, "class_data", None),
+        (0x2001, "code", None),
+        (0x2002, "string_data", None),
+        (0x2003, "debug_info", None),
+        (0x2004, "annotations", None),
+        (0x2005, "encoded_arrays", None),
+        (0x2006, "annotations_directories", None),
+        (0xF000, "hiddenapi_class_data", None),
+    ];
+
+    /// Section name and fixed item size of map item type `type_`, `None` if it is unknown.
+    pub fn map_item_type(type_: u16) -> Option<(&'static str, Option<usize>)> {
+        MAP_ITEM_TYPES.iter().find(|(t, _, _)| *t == type_).map(|&(_, name, size)| (name, size))
+    }
+
     // --- Dex File Data Structs ---
     #[repr(C)]
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Pod, Zeroable)]
@@ -177,11 +350,45 @@ pub mod dex_structs {
         pub insns_size_in_code_units: u32, // size of "insns" in u16 units
     }
 
-    #[allow(dead_code)]
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Pod, Zeroable)]
+    pub struct TryItem {
+        pub start_addr: u32,  // first covered code unit
+        pub insn_count: u16,  // number of covered code units
+        pub handler_off: u16, // byte offset of its handler in the encoded_catch_handler_list
+    }
+
+    /// One `encoded_catch_addr_pair`.
+    #[derive(Debug, Clone, Copy)]
+    pub struct CatchTypeAddr {
+        pub type_idx: u32,
+        pub addr: u32, // code unit of the handler
+    }
+
+    /// One `encoded_catch_handler`.
+    #[derive(Debug, Clone)]
+    pub struct CatchHandler {
+        /// Byte offset in the encoded_catch_handler_list, what `TryItem::handler_off` refers to
+        pub offset: u16,
+        /// Typed handlers, in the order they are tried
+        pub handlers: Vec<CatchTypeAddr>,
+        /// `catch (Throwable)` / `finally`, tried last
+        pub catch_all_addr: Option<u32>,
+    }
+
     #[derive(Debug, Clone)]
     pub struct CodeItem {
         pub header: CodeItemHeader,
         pub insns: Vec<u16>, // Instructions
+        pub tries: Vec<TryItem>,
+        pub handlers: Vec<CatchHandler>,
+    }
+
+    impl CodeItem {
+        /// The handler `try_item` jumps to.
+        pub fn handler_of(&self, try_item: &TryItem) -> Option<&CatchHandler> {
+            self.handlers.iter().find(|h| h.offset == try_item.handler_off)
+        }
     }
 }
 
@@ -189,33 +396,90 @@ pub mod dex_structs {
 
 // --- Parser Implementation ---
 
+/// More items than the format has types is garbage, this many is certainly not a map_list
+const MAX_MAP_ITEMS: usize = 1000;
+/// `superclass_idx` / `source_file_idx` of a class without one
+const NO_INDEX: u32 = 0xffff_ffff;
+
+/// Members of a `class_data_item`, with the index diffs already summed up.
+#[derive(Debug, Default)]
+struct ClassData {
+    /// `(field_idx, access_flags)`
+    static_fields: Vec<(u32, u32)>,
+    instance_fields: Vec<(u32, u32)>,
+    /// `(method_idx, access_flags, code_off)`
+    direct_methods: Vec<(u32, u32, u32)>,
+    virtual_methods: Vec<(u32, u32, u32)>,
+}
+
 /// This is the main public entry point for the parser module.
 /// The scanner will call this function after it has found and
 /// verified a DEX header.
 pub fn parse_dex_at(
-    pid: libc::pid_t,
+    reader: &dyn MemoryReader,
     base_addr: usize,
 ) -> Result<ParsedDexFile, Error> {
     let mut header_buf = [0u8; size_of::<dex_structs::Header>()];
-    if safe_read_memory(pid, base_addr, &mut header_buf).is_err() {
-        ()
+    if reader.read_memory(base_addr, &mut header_buf)? < header_buf.len() {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Truncated DEX header"));
+    }
+    let header: dex_structs::Header = bytemuck::pod_read_unaligned(&header_buf);
+
+    let file_size = header.file_size as usize;
+    if file_size < header_buf.len() || file_size > MAX_DEX_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Invalid DEX file_size 0x{:x}", file_size)));
+    }
+
+    // One bulk read instead of a syscall per struct/byte
+    let data = read_image(reader, base_addr, file_size);
+    parse_dex_bytes(base_addr, &data).map_err(|failure| failure.error)
+}
+
+/// Parses a dex that is already in local memory.
+/// `base_addr` is only recorded in the result, all offsets are relative to `data`.
+pub fn parse_dex_bytes(base_addr: usize, data: &[u8]) -> Result<ParsedDexFile, ParseFailure> {
+    let parser_helper = DexParserHelper::new(data, base_addr);
+    let header = parser_helper
+        .read_struct_at_offset::<dex_structs::Header>(0)
+        .map_err(|error| ParseFailure { error, validation: None })?;
+
+    // Checked first, a forged header usually breaks one of the id tables below
+    let (map_list, validation) = parser_helper.validate(&header);
+    match parser_helper.parse(&header) {
+        Ok(dex_file) => Ok(ParsedDexFile { map_list, validation, ..dex_file }),
+        Err(error) => Err(ParseFailure { error, validation: Some(validation) }),
+    }
+}
+
+/// Reads up to `size` bytes, stopping at the first unreadable address.
+fn read_image(reader: &dyn MemoryReader, base_addr: usize, size: usize) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    let mut filled = 0;
+    while filled < size {
+        match reader.read_memory(base_addr + filled, &mut data[filled..]) {
+            Ok(n) if n > 0 => filled += n,
+            _ => break,
+        }
     }
-    let header: dex_structs::Header = *bytemuck::from_bytes(&header_buf);
+    data.truncate(filled);
+    data
+}
 
-    let parser_helper = DexParserHelper::new(pid, base_addr);
-    parser_helper.parse(&header)
+/// Copies `bytes` into a `Vec<T>`, no matter how `bytes` is aligned.
+fn read_pod_vec<T: bytemuck::Pod>(bytes: &[u8]) -> Vec<T> {
+    bytes.chunks_exact(size_of::<T>()).map(bytemuck::pod_read_unaligned).collect()
 }
 
 /// A private helper struct to manage parsing state.
-/// This is identical to your old `DexParser` struct.
-struct DexParserHelper {
-    pid: libc::pid_t,
+/// Every read is a bounds-checked view into `data`.
+struct DexParserHelper<'a> {
+    data: &'a [u8],
     base_addr: usize,
 }
 
-impl DexParserHelper {
-    fn new(pid: libc::pid_t, base_addr: usize) -> Self {
-        Self { pid, base_addr }
+impl<'a> DexParserHelper<'a> {
+    fn new(data: &'a [u8], base_addr: usize) -> Self {
+        Self { data, base_addr }
     }
 
     /// Main parsing orchestration function.
@@ -280,7 +544,7 @@ impl DexParserHelper {
         )?;
 
         // 9. Parse Methods (Names and Signatures)
-        for (_method_idx, m_id) in method_ids.iter().enumerate() {
+        for m_id in &method_ids {
             let class_name = type_names
                 .get(&(m_id.class_idx as u32))
                 .cloned()
@@ -301,28 +565,26 @@ impl DexParserHelper {
                 class_name,
                 method_name,
                 signature,
-                code_item: None, // Will be filled in by ClassData pass
+                code_item: None, // Will be filled in by build_class
+                access_flags: None,
             });
         }
-        
-        // 10. Parse ClassData (to find CodeItems)
-        let mut method_code_map = HashMap::new(); // map method_idx -> CodeItem
-        for def in &class_defs {
-            if def.class_data_off == 0 { continue; }
 
-            if let Ok(()) = self.parse_class_data(def.class_data_off as usize, &mut method_code_map) {
-                // successfully parsed this class's data
-            }
-        }
-        
-        // 11. Link CodeItems to ParsedMethods
-        for (method_idx, p_method) in methods.iter_mut().enumerate() {
-            if let Some(code) = method_code_map.remove(&(method_idx as u32)) {
-                p_method.code_item = Some(code);
-            }
+        // 10. Parse Classes (ClassData, to find fields and CodeItems of the methods)
+        let mut classes = Vec::with_capacity(class_defs.len());
+        for def in &class_defs {
+            // 0 for a marker interface or a class without members
+            let mut class_data = ClassData::default();
+            let class_data_error = match def.class_data_off {
+                0 => None,
+                off => self.parse_class_data(off as usize, &mut class_data).err(),
+            };
+            let mut class = self.build_class(def, &class_data, &strings, &type_names, &field_ids, &mut methods);
+            class.class_data_error = class_data_error.map(|e| e.to_string());
+            classes.push(class);
         }
 
-        // 12. Build final struct
+        // 11. Build final struct, parse_dex_bytes adds the validation
         Ok(ParsedDexFile {
             base_addr: self.base_addr,
             header: *header,
@@ -335,69 +597,279 @@ impl DexParserHelper {
             strings,
             type_names,
             methods,
+            classes,
+            map_list: None,
+            validation: ValidationReport::default(),
         })
     }
+
+    /// Reads the map_list and checks it, the header's sections and `file_size` against each other.
+    fn validate(&self, header: &dex_structs::Header) -> (Option<dex_structs::MapList>, ValidationReport) {
+        let mut report = ValidationReport::default();
+        let file_size = header.file_size as usize;
+        if file_size > self.data.len() {
+            report.push(
+                IssueKind::Truncated,
+                "header",
+                format!("file_size 0x{:x} but only 0x{:x} bytes could be read", file_size, self.data.len()),
+            );
+        }
+
+        // The id tables as the header places them
+        let mut header_sections = Vec::new();
+        for (type_, size, off) in header_id_sections(header) {
+            let Some((name, Some(item_size))) = dex_structs::map_item_type(type_) else { continue };
+            if size == 0 {
+                continue;
+            }
+            let end = section_end(off, size, item_size);
+            if off < header.header_size as usize || end > file_size {
+                report.push(IssueKind::OutOfRange, name, format!("header places it at 0x{:x}..0x{:x}, outside the file (0x{:x})", off, end, file_size));
+            }
+            header_sections.push((name, off, end));
+        }
+        let data_end = section_end(header.data_off as usize, header.data_size as usize, 1);
+        if header.data_size != 0 && data_end > file_size {
+            report.push(IssueKind::OutOfRange, "data", format!("header places it at 0x{:x}..0x{:x}, outside the file (0x{:x})", header.data_off, data_end, file_size));
+        }
+        check_overlaps(&mut report, "header", header_sections);
+
+        let map_list = self.read_map_list(header.map_off as usize, file_size, &mut report);
+        if let Some(map_list) = &map_list {
+            check_map_list(&mut report, header, map_list);
+        }
+        (map_list, report)
+    }
+
+    /// Reads the map_list at `map_off`, reporting why it could not be.
+    fn read_map_list(&self, map_off: usize, file_size: usize, report: &mut ValidationReport) -> Option<dex_structs::MapList> {
+        if map_off == 0 || map_off + 4 > file_size {
+            report.push(IssueKind::MissingMapList, "map_list", format!("map_off 0x{:x} is outside the file (0x{:x})", map_off, file_size));
+            return None;
+        }
+        let size = match self.read_struct_at_offset::<u32>(map_off) {
+            Ok(size) if size as usize <= MAX_MAP_ITEMS => size,
+            Ok(size) => {
+                report.push(IssueKind::MissingMapList, "map_list", format!("map_list at 0x{:x} claims {} items", map_off, size));
+                return None;
+            }
+            Err(e) => {
+                report.push(IssueKind::MissingMapList, "map_list", e.to_string());
+                return None;
+            }
+        };
+        let end = section_end(map_off + 4, size as usize, size_of::<dex_structs::MapItem>());
+        if end > file_size {
+            report.push(IssueKind::OutOfRange, "map_list", format!("map_list at 0x{:x}..0x{:x} runs past the file (0x{:x})", map_off, end, file_size));
+        }
+        match self.read_struct_vec_at_offset::<dex_structs::MapItem>(map_off + 4, size as usize) {
+            Ok(list) => Some(dex_structs::MapList { size, list }),
+            Err(e) => {
+                report.push(IssueKind::MissingMapList, "map_list", format!("map_list of {} items at 0x{:x}: {}", size, map_off, e));
+                None
+            }
+        }
+    }
     
-    /// Parses the `class_data_item` for a ClassDef.
-    fn parse_class_data(&self, offset: usize, method_code_map: &mut HashMap<u32, dex_structs::CodeItem>) -> Result<(), Error> {
+    /// Parses the `class_data_item` for a ClassDef into `class_data`, which keeps the
+    /// members decoded before an error.
+    fn parse_class_data(&self, offset: usize, class_data: &mut ClassData) -> Result<(), Error> {
         let mut current_offset = offset;
         let (static_fields_size, _) = self.read_uleb128_and_size(&mut current_offset)?;
         let (instance_fields_size, _) = self.read_uleb128_and_size(&mut current_offset)?;
         let (direct_methods_size, _) = self.read_uleb128_and_size(&mut current_offset)?;
         let (virtual_methods_size, _) = self.read_uleb128_and_size(&mut current_offset)?;
 
-        // Skip fields
-        for _ in 0..static_fields_size {
-            self.read_uleb128_and_size(&mut current_offset)?; // field_idx_diff
-            self.read_uleb128_and_size(&mut current_offset)?; // access_flags
-        }
-        for _ in 0..instance_fields_size {
-            self.read_uleb128_and_size(&mut current_offset)?; // field_idx_diff
-            self.read_uleb128_and_size(&mut current_offset)?; // access_flags
-        }
-
-        // Parse direct methods
-        let mut last_method_idx = 0;
-        for _ in 0..direct_methods_size {
-            let (method_idx_diff, _) = self.read_uleb128_and_size(&mut current_offset)?;
-            let (_access_flags, _) = self.read_uleb128_and_size(&mut current_offset)?;
-            let (code_off, _) = self.read_uleb128_and_size(&mut current_offset)?;
-            
-            last_method_idx += method_idx_diff;
-            if code_off != 0 {
-                if let Ok(code_item) = self.parse_code_item(code_off as usize) {
-                    method_code_map.insert(last_method_idx, code_item);
-                }
+        // Each list starts its index diffs from 0
+        for (size, fields) in [(static_fields_size, &mut class_data.static_fields), (instance_fields_size, &mut class_data.instance_fields)] {
+            let mut last_field_idx = 0u32;
+            for _ in 0..size {
+                let (field_idx_diff, _) = self.read_uleb128_and_size(&mut current_offset)?;
+                let (access_flags, _) = self.read_uleb128_and_size(&mut current_offset)?;
+                last_field_idx = last_field_idx.wrapping_add(field_idx_diff);
+                fields.push((last_field_idx, access_flags));
             }
         }
-
-        // Parse virtual methods
-        last_method_idx = 0;
-        for _ in 0..virtual_methods_size {
-            let (method_idx_diff, _) = self.read_uleb128_and_size(&mut current_offset)?;
-            let (_access_flags, _) = self.read_uleb128_and_size(&mut current_offset)?;
-            let (code_off, _) = self.read_uleb128_and_size(&mut current_offset)?;
-            
-            last_method_idx += method_idx_diff;
-            if code_off != 0 {
-                if let Ok(code_item) = self.parse_code_item(code_off as usize) {
-                    method_code_map.insert(last_method_idx, code_item);
-                }
+        for (size, methods) in [(direct_methods_size, &mut class_data.direct_methods), (virtual_methods_size, &mut class_data.virtual_methods)] {
+            let mut last_method_idx = 0u32;
+            for _ in 0..size {
+                let (method_idx_diff, _) = self.read_uleb128_and_size(&mut current_offset)?;
+                let (access_flags, _) = self.read_uleb128_and_size(&mut current_offset)?;
+                let (code_off, _) = self.read_uleb128_and_size(&mut current_offset)?;
+                last_method_idx = last_method_idx.wrapping_add(method_idx_diff);
+                methods.push((last_method_idx, access_flags, code_off));
             }
         }
         Ok(())
     }
 
-    /// Parses a `CodeItem` at a given offset.
-    fn parse_code_item(&self, offset: usize) -> Result<dex_structs::CodeItem, Error> {
+    /// Resolves the names of a ClassDef and its members, and fills in the CodeItem and flags
+    /// of each of its methods in `methods`.
+    fn build_class(
+        &self,
+        def: &dex_structs::ClassDef,
+        class_data: &ClassData,
+        strings: &HashMap<u32, String>,
+        type_names: &HashMap<u32, String>,
+        field_ids: &[dex_structs::FieldId],
+        methods: &mut [ParsedMethod],
+    ) -> ParsedClass {
+        let type_name = |idx: u32| type_names.get(&idx).cloned().unwrap_or_else(|| "??".to_string());
+
+        let interfaces = match def.interfaces_off {
+            0 => Vec::new(),
+            off => self
+                .parse_type_list(off as usize)
+                .map(|list| list.into_iter().map(|idx| type_name(idx as u32)).collect())
+                .unwrap_or_default(),
+        };
+
+        let fields = |members: &[(u32, u32)]| -> Vec<ParsedField> {
+            members
+                .iter()
+                .map(|&(field_idx, access_flags)| {
+                    let id = field_ids.get(field_idx as usize);
+                    ParsedField {
+                        field_idx,
+                        name: id.and_then(|id| strings.get(&id.name_idx)).cloned().unwrap_or_else(|| "??".to_string()),
+                        type_name: id.map_or_else(|| "??".to_string(), |id| type_name(id.type_idx as u32)),
+                        access_flags,
+                    }
+                })
+                .collect()
+        };
+
+        // The CodeItem is kept once, on the ParsedMethod of its method_idx
+        let mut class_methods = |members: &[(u32, u32, u32)]| -> Vec<ParsedClassMethod> {
+            members
+                .iter()
+                .map(|&(method_idx, access_flags, code_off)| {
+                    let Some(method) = methods.get_mut(method_idx as usize) else {
+                        return ParsedClassMethod {
+                            method_idx,
+                            name: "??".to_string(),
+                            signature: "()?".to_string(),
+                            access_flags,
+                            code_error: (code_off != 0).then(|| format!("method_idx {} is out of range", method_idx)),
+                        };
+                    };
+                    let (code_item, code_error) = match code_off {
+                        0 => (None, None),
+                        off => match self.parse_code_item(off as usize) {
+                            Ok((code_item, tries_error)) => (Some(code_item), tries_error.map(|e| e.to_string())),
+                            Err(e) => (None, Some(e.to_string())),
+                        },
+                    };
+                    method.code_item = code_item;
+                    method.access_flags = Some(access_flags);
+                    ParsedClassMethod {
+                        method_idx,
+                        name: method.method_name.clone(),
+                        signature: method.signature.clone(),
+                        access_flags,
+                        code_error,
+                    }
+                })
+                .collect()
+        };
+
+        ParsedClass {
+            class_idx: def.class_idx,
+            descriptor: type_name(def.class_idx),
+            access_flags: def.access_flags,
+            superclass: (def.superclass_idx != NO_INDEX).then(|| type_name(def.superclass_idx)),
+            interfaces,
+            source_file: (def.source_file_idx != NO_INDEX).then(|| strings.get(&def.source_file_idx).cloned()).flatten(),
+            static_fields: fields(&class_data.static_fields),
+            instance_fields: fields(&class_data.instance_fields),
+            direct_methods: class_methods(&class_data.direct_methods),
+            virtual_methods: class_methods(&class_data.virtual_methods),
+            class_data_error: None,
+        }
+    }
+
+    /// Parses a `CodeItem` at a given offset. Malformed try blocks or handlers do not cost the
+    /// instructions: the item keeps them, without tries and handlers, next to the error.
+    fn parse_code_item(&self, offset: usize) -> Result<(dex_structs::CodeItem, Option<Error>), Error> {
         let header = self.read_struct_at_offset::<dex_structs::CodeItemHeader>(offset)?;
         let insns_offset = offset + size_of::<dex_structs::CodeItemHeader>();
         let insns_size_bytes = header.insns_size_in_code_units as usize * 2; // 2 bytes per u16
         
         let insns_bytes = self.read_bytes(insns_offset, insns_size_bytes)?;
-        let insns: Vec<u16> = bytemuck::cast_slice::<u8, u16>(&insns_bytes).to_vec();
+        let insns: Vec<u16> = read_pod_vec(insns_bytes);
+
+        if header.tries_size == 0 {
+            return Ok((dex_structs::CodeItem { header, insns, tries: Vec::new(), handlers: Vec::new() }, None));
+        }
+
+        // try_items are 4-aligned: an odd insns_size is followed by two bytes of padding
+        let tries_offset = insns_offset + insns_size_bytes + (header.insns_size_in_code_units as usize % 2) * 2;
+        match self.parse_tries(&header, tries_offset) {
+            Ok((tries, handlers)) => Ok((dex_structs::CodeItem { header, insns, tries, handlers }, None)),
+            Err(e) => Ok((dex_structs::CodeItem { header, insns, tries: Vec::new(), handlers: Vec::new() }, Some(e))),
+        }
+    }
+
+    /// Parses the `try_item`s at `offset` and the handler list after them, checking that every
+    /// try stays within the code and points at a handler.
+    fn parse_tries(
+        &self,
+        header: &dex_structs::CodeItemHeader,
+        offset: usize,
+    ) -> Result<(Vec<dex_structs::TryItem>, Vec<dex_structs::CatchHandler>), Error> {
+        let tries = self.read_struct_vec_at_offset::<dex_structs::TryItem>(offset, header.tries_size as usize)?;
+        let handlers_offset = offset + tries.len() * size_of::<dex_structs::TryItem>();
+        let handlers = self.parse_catch_handlers(handlers_offset)?;
 
-        Ok(dex_structs::CodeItem { header, insns })
+        for (i, try_item) in tries.iter().enumerate() {
+            if try_item.start_addr as u64 + try_item.insn_count as u64 > header.insns_size_in_code_units as u64 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("try #{} covers 0x{:x}+0x{:x}, past the 0x{:x} code units", i, try_item.start_addr, try_item.insn_count, header.insns_size_in_code_units),
+                ));
+            }
+            if !handlers.iter().any(|h| h.offset == try_item.handler_off) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("try #{} handler_off 0x{:x} is not the start of a handler", i, try_item.handler_off),
+                ));
+            }
+        }
+
+        Ok((tries, handlers))
+    }
+
+    /// Parses the `encoded_catch_handler_list` at `offset`.
+    fn parse_catch_handlers(&self, offset: usize) -> Result<Vec<dex_structs::CatchHandler>, Error> {
+        let mut current_offset = offset;
+        let (list_size, _) = self.read_uleb128_and_size(&mut current_offset)?;
+        if list_size == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Empty catch handler list"));
+        }
+
+        let mut handlers = Vec::new();
+        for _ in 0..list_size {
+            // try_item.handler_off is a u16, no handler can start further away
+            let handler_offset = u16::try_from(current_offset - offset)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Catch handler list longer than 64 KiB"))?;
+
+            // size > 0: that many typed handlers; size <= 0: -size typed handlers plus a catch-all
+            let size = self.read_sleb128(&mut current_offset)?;
+            let mut typed = Vec::new();
+            for _ in 0..size.unsigned_abs() {
+                let (type_idx, _) = self.read_uleb128_and_size(&mut current_offset)?;
+                let (addr, _) = self.read_uleb128_and_size(&mut current_offset)?;
+                typed.push(dex_structs::CatchTypeAddr { type_idx, addr });
+            }
+            let catch_all_addr = if size <= 0 {
+                Some(self.read_uleb128_and_size(&mut current_offset)?.0)
+            } else {
+                None
+            };
+
+            handlers.push(dex_structs::CatchHandler { offset: handler_offset, handlers: typed, catch_all_addr });
+        }
+        Ok(handlers)
     }
     
     /// Resolves a ProtoId into a readable signature string.
@@ -425,56 +897,53 @@ impl DexParserHelper {
         }
         let list_offset = offset + 4; // after size
         let list_bytes = self.read_bytes(list_offset, size * 2)?; // u16
-        let list: Vec<u16> = bytemuck::cast_slice::<u8, u16>(&list_bytes).to_vec();
+        let list: Vec<u16> = read_pod_vec(list_bytes);
         Ok(list)
     }
 
-    // --- Memory Read Helpers ---
+    // --- Buffer Read Helpers ---
 
-    /// Reads raw bytes from `base_addr + offset`.
-    fn read_bytes(&self, offset: usize, size: usize) -> Result<Vec<u8>, Error> {
-        if size == 0 { return Ok(Vec::new()); }
-        let mut buf = vec![0u8; size];
-        safe_read_memory(self.pid, self.base_addr + offset, &mut buf)?;
-        Ok(buf)
+    /// Returns `data[offset..offset + size]`, or an error if it is out of bounds.
+    fn read_bytes(&self, offset: usize, size: usize) -> Result<&'a [u8], Error> {
+        offset
+            .checked_add(size)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, format!("Read of 0x{:x} bytes at offset 0x{:x} out of bounds", size, offset)))
     }
 
-    /// Reads raw bytes into an existing buffer.
-    fn read_bytes_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, Error> {
-        safe_read_memory(self.pid, self.base_addr + offset, buf)
+    /// Reads a single byte at `offset`.
+    fn read_u8(&self, offset: usize) -> Result<u8, Error> {
+        self.data
+            .get(offset)
+            .copied()
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, format!("Offset 0x{:x} out of bounds", offset)))
     }
 
-    /// Reads a struct T from `base_addr + offset`.
+    /// Reads a struct T at `offset`.
     fn read_struct_at_offset<T: bytemuck::Pod>(&self, offset: usize) -> Result<T, Error> {
-        let mut buf = vec![0u8; size_of::<T>()];
-        self.read_bytes_at(offset, &mut buf)?;
-        Ok(*bytemuck::from_bytes::<T>(&buf))
+        let bytes = self.read_bytes(offset, size_of::<T>())?;
+        Ok(bytemuck::pod_read_unaligned::<T>(bytes))
     }
 
-    /// Reads a `count` number of structs T from `base_addr + offset`.
+    /// Reads a `count` number of structs T at `offset`.
     fn read_struct_vec_at_offset<T: bytemuck::Pod>(&self, offset: usize, count: usize) -> Result<Vec<T>, Error> {
         let total_size = size_of::<T>().checked_mul(count).ok_or_else(|| Error::new(ErrorKind::InvalidData, "Struct vector size overflow"))?;
         if total_size == 0 {
             return Ok(Vec::new());
         }
-        if total_size > 100 * 1024 * 1024 { // 100MB sanity limit
-            return Err(Error::new(ErrorKind::InvalidData, "Struct vector size too large"));
-        }
         let bytes = self.read_bytes(offset, total_size)?;
-        Ok(bytemuck::cast_slice::<u8, T>(&bytes).to_vec())
+        Ok(read_pod_vec(bytes))
     }
     
     /// Reads a ULEB128-encoded value and returns it and the number of bytes read.
     fn read_uleb128_and_size(&self, offset: &mut usize) -> Result<(u32, usize), Error> {
         let mut result: u32 = 0;
         let mut shift = 0;
-        let mut byte_buf = [0u8; 1];
         let start_offset = *offset;
 
         loop {
-            self.read_bytes_at(*offset, &mut byte_buf)?;
+            let byte = self.read_u8(*offset)?;
             *offset += 1;
-            let byte = byte_buf[0];
 
             result |= ((byte & 0x7F) as u32) << shift;
             if (byte & 0x80) == 0 {
@@ -488,28 +957,49 @@ impl DexParserHelper {
         Ok((result, *offset - start_offset))
     }
 
+    /// Reads a SLEB128-encoded value.
+    fn read_sleb128(&self, offset: &mut usize) -> Result<i32, Error> {
+        let mut result: i32 = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.read_u8(*offset)?;
+            *offset += 1;
+
+            result |= ((byte & 0x7F) as i32) << shift;
+            shift += 7;
+            if (byte & 0x80) == 0 {
+                // Sign-extend from the last byte's bit 6
+                if shift < 32 && (byte & 0x40) != 0 {
+                    result |= -1 << shift;
+                }
+                break;
+            }
+            if shift > 28 { // 5 bytes max for i32
+                return Err(Error::new(ErrorKind::InvalidData, "Invalid SLEB128"));
+            }
+        }
+        Ok(result)
+    }
+
     /// Reads a MUTF-8 string.
     fn read_string_data(&self, offset: usize) -> Result<(String, usize), Error> {
         let mut current_offset = offset;
         let (utf16_len, len_bytes_read) = self.read_uleb128_and_size(&mut current_offset)?;
-        
-        let mut str_bytes = Vec::new();
-        let mut byte_buf = [0u8; 1];
-        let mut bytes_read_for_str = 0;
-        loop {
-            self.read_bytes_at(current_offset, &mut byte_buf)?;
-            current_offset += 1;
-            bytes_read_for_str += 1;
-            
-            if byte_buf[0] == 0 {
-                break;
+
+        let max_len = utf16_len as usize * 3 + 10;
+        let tail = self.data.get(current_offset..).unwrap_or_default();
+        let str_len = match tail.iter().take(max_len + 1).position(|b| *b == 0) {
+            Some(len) => len,
+            None if tail.len() <= max_len => {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "Unterminated MUTF-8 string"));
             }
-            str_bytes.push(byte_buf[0]);
-            
-            if bytes_read_for_str > (utf16_len as usize * 3 + 10) { 
-                 return Err(Error::new(ErrorKind::InvalidData, "MUTF-8 string too long"));
+            None => {
+                return Err(Error::new(ErrorKind::InvalidData, "MUTF-8 string too long"));
             }
-        }
+        };
+        let str_bytes = &tail[..str_len];
+        let bytes_read_for_str = str_len + 1; // including the NUL
 
         // Simple MUTF-8 (C0 80 -> 00) replacement
         let mut i = 0;
@@ -528,4 +1018,98 @@ impl DexParserHelper {
         let total_bytes_read = len_bytes_read + bytes_read_for_str;
         Ok((s, total_bytes_read))
     }
-}
\ No newline at end of file
+}
+
+/// `(map item type, size, off)` of the id tables the header points at.
+fn header_id_sections(header: &dex_structs::Header) -> [(u16, usize, usize); 6] {
+    use dex_structs::*;
+    [
+        (TYPE_STRING_ID_ITEM, header.string_ids_size as usize, header.string_ids_off as usize),
+        (TYPE_TYPE_ID_ITEM, header.type_ids_size as usize, header.type_ids_off as usize),
+        (TYPE_PROTO_ID_ITEM, header.proto_ids_size as usize, header.proto_ids_off as usize),
+        (TYPE_FIELD_ID_ITEM, header.field_ids_size as usize, header.field_ids_off as usize),
+        (TYPE_METHOD_ID_ITEM, header.method_ids_size as usize, header.method_ids_off as usize),
+        (TYPE_CLASS_DEF_ITEM, header.class_defs_size as usize, header.class_defs_off as usize),
+    ]
+}
+
+/// End of `count` items of `item_size` bytes from `off`, saturating instead of wrapping.
+fn section_end(off: usize, count: usize, item_size: usize) -> usize {
+    off.saturating_add(count.saturating_mul(item_size))
+}
+
+/// Checks the items of `map_list` on their own and against the header.
+fn check_map_list(report: &mut ValidationReport, header: &dex_structs::Header, map_list: &dex_structs::MapList) {
+    let file_size = header.file_size as usize;
+    let mut seen = Vec::new();
+    let mut previous_off = None;
+    let mut sections = Vec::new();
+    for item in &map_list.list {
+        let Some((name, item_size)) = dex_structs::map_item_type(item.type_) else {
+            report.push(IssueKind::UnknownType, "map_list", format!("unknown item type 0x{:04x} at 0x{:x}", item.type_, item.offset));
+            continue;
+        };
+        if seen.contains(&item.type_) {
+            report.push(IssueKind::DuplicateType, name, "listed twice in the map_list".to_string());
+        }
+        seen.push(item.type_);
+        if previous_off.is_some_and(|previous| item.offset <= previous) {
+            report.push(IssueKind::Unsorted, name, format!("map_list offset 0x{:x} is not after the previous item", item.offset));
+        }
+        previous_off = Some(item.offset);
+
+        // Variable-size items only have a start, they can still be run into
+        let off = item.offset as usize;
+        let end = item_size.map_or(off, |item_size| section_end(off, item.size as usize, item_size));
+        if off >= file_size || end > file_size {
+            report.push(IssueKind::OutOfRange, name, format!("map_list places it at 0x{:x}..0x{:x}, outside the file (0x{:x})", off, end, file_size));
+        }
+        sections.push((name, off, end));
+    }
+    check_overlaps(report, "map_list", sections);
+
+    let find = |type_: u16| map_list.list.iter().find(|item| item.type_ == type_);
+    if find(dex_structs::TYPE_HEADER_ITEM).is_none_or(|item| item.offset != 0 || item.size != 1) {
+        report.push(IssueKind::OffsetMismatch, "header", "map_list does not list the header at 0".to_string());
+    }
+    match find(dex_structs::TYPE_MAP_LIST) {
+        Some(item) if item.offset != header.map_off => report.push(
+            IssueKind::OffsetMismatch,
+            "map_list",
+            format!("map_list lists itself at 0x{:x}, map_off is 0x{:x}", item.offset, header.map_off),
+        ),
+        Some(_) => {}
+        None => report.push(IssueKind::MissingSection, "map_list", "map_list does not list itself".to_string()),
+    }
+
+    for (type_, size, off) in header_id_sections(header) {
+        let Some((name, _)) = dex_structs::map_item_type(type_) else { continue };
+        let Some(item) = find(type_) else {
+            if size > 0 {
+                report.push(IssueKind::MissingSection, name, format!("header declares {} at 0x{:x}, the map_list has none", size, off));
+            }
+            continue;
+        };
+        if item.size as usize != size {
+            report.push(IssueKind::CountMismatch, name, format!("header declares {}, the map_list {}", size, item.size));
+        }
+        if item.offset as usize != off {
+            report.push(IssueKind::OffsetMismatch, name, format!("header places it at 0x{:x}, the map_list at 0x{:x}", off, item.offset));
+        }
+    }
+}
+
+/// Reports every section of `sections` (name, start, end) that runs into the next one by start.
+fn check_overlaps(report: &mut ValidationReport, origin: &str, mut sections: Vec<(&str, usize, usize)>) {
+    sections.sort_by_key(|&(_, start, _)| start);
+    for pair in sections.windows(2) {
+        let ((name, start, end), (next, next_start, _)) = (pair[0], pair[1]);
+        if end > next_start {
+            report.push(
+                IssueKind::Overlap,
+                name,
+                format!("{} places it at 0x{:x}..0x{:x}, running into {} at 0x{:x}", origin, start, end, next, next_start),
+            );
+        }
+    }
+}
@@ -0,0 +1,494 @@
+// dex_reconstruct.rs
+//
+// Some packers zero `file_size`, `map_off` and the id table offsets of a loaded dex, so only the
+// sections themselves are left. Their own structure is usually enough to rebuild the header:
+//   1. the map_list, which lists every section and, as the `map_list` item, itself at its own offset
+//   2. failing that, the string_ids array right after the header, sorted and pointing at valid
+//      string data, and the type_ids array behind it
+
+use super::dex_dumper::{mapped_extent, page_size};
+use super::dex_parser::dex_structs::{
+    map_item_type, MAP_ITEM_TYPES, TYPE_CLASS_DEF_ITEM, TYPE_FIELD_ID_ITEM, TYPE_MAP_LIST, TYPE_METHOD_ID_ITEM,
+    TYPE_PROTO_ID_ITEM, TYPE_STRING_ID_ITEM, TYPE_TYPE_ID_ITEM,
+};
+use super::dex_repair::HeaderFix;
+use super::maps::MapRange;
+use super::memory_reader::MemoryReader;
+use serde::Serialize;
+use std::cmp::Ordering;
+
+const HEADER_SIZE: usize = 0x70;
+const HEADER_SIZE_V41: usize = 0x78;
+const ENDIAN_CONSTANT: u32 = 0x12345678;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+const MAP_ITEM_SIZE: usize = 12;
+/// The first map item is always the header: type 0, unused 0, size 1, offset 0
+const HEADER_MAP_ITEM: [u8; MAP_ITEM_SIZE] = [0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+
+/// Header fields a reconstruction may rewrite, with their offset
+const OFF_FILE_SIZE: usize = 0x20;
+const OFF_HEADER_SIZE: usize = 0x24;
+const OFF_ENDIAN_TAG: usize = 0x28;
+const OFF_LINK_SIZE: usize = 0x2c;
+const OFF_LINK_OFF: usize = 0x30;
+const OFF_MAP_OFF: usize = 0x34;
+/// `*_size` / `*_off` pairs filled from the map item of the same type
+const ID_SECTIONS: [(u16, &str, &str, usize); 6] = [
+    (TYPE_STRING_ID_ITEM, "string_ids_size", "string_ids_off", 0x38),
+    (TYPE_TYPE_ID_ITEM, "type_ids_size", "type_ids_off", 0x40),
+    (TYPE_PROTO_ID_ITEM, "proto_ids_size", "proto_ids_off", 0x48),
+    (TYPE_FIELD_ID_ITEM, "field_ids_size", "field_ids_off", 0x50),
+    (TYPE_METHOD_ID_ITEM, "method_ids_size", "method_ids_off", 0x58),
+    (TYPE_CLASS_DEF_ITEM, "class_defs_size", "class_defs_off", 0x60),
+];
+const OFF_DATA_SIZE: usize = 0x68;
+const OFF_DATA_OFF: usize = 0x6c;
+
+/// Strings and types read to check the id tables of a map_list reconstruction
+const ID_SAMPLE: usize = 16;
+/// Fewer sorted strings than this are not taken as a string_ids array
+const MIN_STRING_IDS: usize = 8;
+/// Longest string the id table checks read
+const MAX_STRING_SIZE: usize = 64 * 1024;
+
+/// What the header was rebuilt from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconstructionMethod {
+    /// A map_list that lists itself at its own offset; every section and the exact extent
+    MapList,
+    /// The string_ids and type_ids arrays after the header; the extent is a lower bound
+    StringIds,
+}
+
+/// A header rebuilt for a dex whose own one was wiped.
+#[derive(Debug, Clone, Serialize)]
+pub struct Reconstruction {
+    pub method: ReconstructionMethod,
+    /// Share of the consistency checks that passed: 0.5 to 1.0 from a map_list,
+    /// at most 0.5 from string_ids
+    pub confidence: f32,
+    /// Header fields that differ from memory, rewritten in the dump
+    pub inferred_fields: Vec<HeaderFix>,
+    /// Consistency checks that did not pass
+    pub failed_checks: Vec<&'static str>,
+    /// Extent of the dex, the `file_size` written to the dump
+    #[serde(skip)]
+    pub file_size: usize,
+    #[serde(skip)]
+    patches: Vec<(usize, u32)>,
+}
+
+impl Reconstruction {
+    /// Writes the inferred fields into the header of `data`, a copy of the dex.
+    pub fn apply(&self, data: &mut [u8]) {
+        for &(offset, value) in &self.patches {
+            if let Some(field) = data.get_mut(offset..offset + 4) {
+                field.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Rebuilds the header of the candidate at `addr`, whose first `HEADER_SIZE` bytes are `header`.
+/// Looks no further than `max_dex_size` and the end of the mappings from `addr`.
+pub fn reconstruct(
+    reader: &dyn MemoryReader,
+    addr: usize,
+    header: &[u8; HEADER_SIZE],
+    ranges: &[MapRange],
+    max_dex_size: usize,
+) -> Option<Reconstruction> {
+    let extent = mapped_extent(ranges, addr).min(max_dex_size);
+    if extent <= HEADER_SIZE {
+        return None;
+    }
+    let image = Image { reader, addr, extent };
+
+    match find_map_list(&image) {
+        Some((map_off, items)) => Some(from_map_list(&image, header, map_off, &items)),
+        None => from_string_ids(&image, header),
+    }
+}
+
+/// The candidate, readable up to `extent`.
+struct Image<'a> {
+    reader: &'a dyn MemoryReader,
+    addr: usize,
+    extent: usize,
+}
+
+impl Image<'_> {
+    /// Reads `buf.len()` bytes at `offset`, all or nothing.
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Option<()> {
+        if offset + buf.len() > self.extent {
+            return None;
+        }
+        let n = self.reader.read_memory(self.addr + offset, buf).ok()?;
+        (n == buf.len()).then_some(())
+    }
+
+    fn read_u32s(&self, offset: usize, count: usize) -> Option<Vec<u32>> {
+        let mut buf = vec![0u8; count * 4];
+        self.read(offset, &mut buf)?;
+        Some(buf.chunks_exact(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+
+    /// The MUTF-8 bytes of the string_data_item at `offset` and the offset right after it,
+    /// if its length prefix matches the characters up to the terminating NUL.
+    fn read_string(&self, offset: usize) -> Option<(Vec<u8>, usize)> {
+        let mut prefix = [0u8; 5];
+        let prefix_len = prefix.len().min(self.extent.checked_sub(offset)?);
+        self.read(offset, &mut prefix[..prefix_len])?;
+        let (utf16_size, uleb_len) = read_uleb128(&prefix[..prefix_len])?;
+
+        // Every UTF-16 unit takes one to three bytes, plus the NUL
+        let max_len = (utf16_size as usize * 3 + 1).min(MAX_STRING_SIZE).min(self.extent - offset - uleb_len);
+        let mut bytes = vec![0u8; max_len];
+        self.read(offset + uleb_len, &mut bytes)?;
+        let len = mutf8_len(&bytes, utf16_size as usize)?;
+        bytes.truncate(len);
+        Some((bytes, offset + uleb_len + len + 1))
+    }
+}
+
+/// Looks for the map_list from just after the header to the end of the image.
+fn find_map_list(image: &Image) -> Option<(usize, Vec<[u8; MAP_ITEM_SIZE]>)> {
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut offset = HEADER_SIZE;
+    while offset + 4 + MAP_ITEM_SIZE <= image.extent {
+        let len = CHUNK_SIZE.min(image.extent - offset);
+        let n = image.reader.read_memory(image.addr + offset, &mut buf[..len]).unwrap_or(0);
+        if n == 0 {
+            // Unreadable page, go on at the next one
+            let page = page_size();
+            offset += page - (image.addr + offset) % page;
+            continue;
+        }
+
+        let mut i = 0;
+        while i + 4 + MAP_ITEM_SIZE <= n {
+            if buf[i + 4..i + 4 + MAP_ITEM_SIZE] == HEADER_MAP_ITEM
+                && let Some(items) = read_map_list(image, offset + i)
+            {
+                return Some((offset + i, items));
+            }
+            i += 4;
+        }
+        // Overlap the next chunk by a count plus one item, a map_list may straddle them
+        offset += n.saturating_sub(4 + MAP_ITEM_SIZE).max(4) & !3;
+    }
+    None
+}
+
+/// The items of a plausible map_list at `map_off`: known types, each at most once, at strictly
+/// ascending offsets inside the image, the first being the header and one being itself.
+fn read_map_list(image: &Image, map_off: usize) -> Option<Vec<[u8; MAP_ITEM_SIZE]>> {
+    let count = image.read_u32s(map_off, 1)?[0] as usize;
+    if !(3..=MAP_ITEM_TYPES.len()).contains(&count) {
+        return None;
+    }
+    let mut bytes = vec![0u8; count * MAP_ITEM_SIZE];
+    image.read(map_off + 4, &mut bytes)?;
+    let items: Vec<[u8; MAP_ITEM_SIZE]> = bytes.chunks_exact(MAP_ITEM_SIZE).map(|b| b.try_into().unwrap()).collect();
+
+    let mut seen = Vec::with_capacity(count);
+    let mut previous_offset = None;
+    let mut lists_itself = false;
+    for item in &items {
+        let (type_, unused, size, offset) = map_item(item);
+        if unused != 0 || size == 0 || seen.contains(&type_) || map_item_type(type_).is_none() {
+            return None;
+        }
+        if previous_offset.is_some_and(|previous| offset <= previous) || offset >= image.extent {
+            return None;
+        }
+        // A garbage size on a fixed-size section would stretch file_size past the mapping
+        if let Some((_, Some(item_size))) = map_item_type(type_)
+            && size.checked_mul(item_size).and_then(|len| offset.checked_add(len)).is_none_or(|end| end > image.extent)
+        {
+            return None;
+        }
+        lists_itself |= type_ == TYPE_MAP_LIST && size == 1 && offset == map_off;
+        seen.push(type_);
+        previous_offset = Some(offset);
+    }
+    lists_itself.then_some(items)
+}
+
+/// `(type, unused, size, offset)`
+fn map_item(item: &[u8; MAP_ITEM_SIZE]) -> (u16, u16, usize, usize) {
+    let u16_at = |i: usize| u16::from_le_bytes([item[i], item[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes(item[i..i + 4].try_into().unwrap()) as usize;
+    (u16_at(0), u16_at(2), u32_at(4), u32_at(8))
+}
+
+/// Field values derived from the sections, collected before they are compared with memory.
+struct HeaderBuilder<'a> {
+    header: &'a [u8; HEADER_SIZE],
+    fields: Vec<(&'static str, usize, u32)>,
+}
+
+impl<'a> HeaderBuilder<'a> {
+    fn new(header: &'a [u8; HEADER_SIZE]) -> Self {
+        Self { header, fields: Vec::new() }
+    }
+
+    fn old(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.header[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn set(&mut self, name: &'static str, offset: usize, value: usize) {
+        self.fields.push((name, offset, value as u32));
+    }
+
+    /// The `*_size` / `*_off` pair of the id table of map item type `type_`.
+    fn set_section(&mut self, type_: u16, size: usize, off: usize) {
+        if let Some(&(_, size_name, off_name, offset)) = ID_SECTIONS.iter().find(|section| section.0 == type_) {
+            self.set(size_name, offset, size);
+            self.set(off_name, offset + 4, off);
+        }
+    }
+
+    /// Whether no field that survived in memory (non-zero) disagrees with the derived value.
+    fn agrees(&self) -> bool {
+        self.fields.iter().all(|&(_, offset, value)| self.old(offset) == 0 || self.old(offset) == value)
+    }
+
+    fn finish(self, method: ReconstructionMethod, file_size: usize, passed: &[(&'static str, bool)]) -> Reconstruction {
+        let mut inferred_fields = Vec::new();
+        let mut patches = Vec::new();
+        for &(name, offset, value) in &self.fields {
+            let old = self.old(offset);
+            if old != value {
+                inferred_fields.push(HeaderFix {
+                    field: name,
+                    old_value: format!("0x{:x}", old),
+                    new_value: format!("0x{:x}", value),
+                });
+                patches.push((offset, value));
+            }
+        }
+
+        let score = passed.iter().filter(|(_, ok)| *ok).count() as f32 / passed.len() as f32;
+        let confidence = match method {
+            ReconstructionMethod::MapList => 0.5 + score / 2.0,
+            ReconstructionMethod::StringIds => score / 2.0,
+        };
+        let confidence = (confidence * 100.0).round() / 100.0;
+        let failed_checks = passed.iter().filter(|(_, ok)| !ok).map(|(name, _)| *name).collect();
+        Reconstruction { method, confidence, inferred_fields, failed_checks, file_size, patches }
+    }
+}
+
+/// Every header field from the map_list at `map_off`.
+fn from_map_list(image: &Image, header: &[u8; HEADER_SIZE], map_off: usize, items: &[[u8; MAP_ITEM_SIZE]]) -> Reconstruction {
+    let items: Vec<(u16, u16, usize, usize)> = items.iter().map(map_item).collect();
+    let section = |type_: u16| items.iter().find(|item| item.0 == type_);
+    let map_end = map_off + 4 + items.len() * MAP_ITEM_SIZE;
+
+    // Where fixed-size sections end; they must not run into the next one
+    let mut disjoint = true;
+    let mut known_end = map_end;
+    for (i, &(type_, _, size, offset)) in items.iter().enumerate() {
+        let item_size = map_item_type(type_).and_then(|(_, size)| size);
+        let Some(item_size) = item_size else { continue };
+        let end = offset + size * item_size;
+        disjoint &= items.get(i + 1).is_none_or(|next| end <= next.3);
+        known_end = known_end.max(end);
+    }
+
+    // Past the map_list only the header can say how far a variable-size section goes
+    let mut builder = HeaderBuilder::new(header);
+    let map_is_last = items.last().is_some_and(|item| item.0 == TYPE_MAP_LIST);
+    let old_file_size = builder.old(OFF_FILE_SIZE) as usize;
+    let file_size = if !map_is_last && old_file_size >= known_end && old_file_size <= image.extent {
+        old_file_size
+    } else {
+        known_end
+    };
+    let extent_known = map_is_last || file_size == old_file_size;
+
+    let header_size = match builder.old(OFF_HEADER_SIZE) as usize {
+        HEADER_SIZE_V41 if &header[4..7] == b"041" => HEADER_SIZE_V41,
+        _ => HEADER_SIZE,
+    };
+    // Everything that is not the header or an id table
+    let data_off = items.iter().find(|item| item.0 >= TYPE_MAP_LIST).map_or(map_off, |item| item.3);
+
+    builder.set("file_size", OFF_FILE_SIZE, file_size);
+    builder.set("header_size", OFF_HEADER_SIZE, header_size);
+    builder.set("endian_tag", OFF_ENDIAN_TAG, ENDIAN_CONSTANT as usize);
+    let (link_size, link_off) = (builder.old(OFF_LINK_SIZE) as usize, builder.old(OFF_LINK_OFF) as usize);
+    if link_size == 0 || link_off == 0 || link_off + link_size > file_size {
+        builder.set("link_size", OFF_LINK_SIZE, 0);
+        builder.set("link_off", OFF_LINK_OFF, 0);
+    }
+    builder.set("map_off", OFF_MAP_OFF, map_off);
+    for (type_, ..) in ID_SECTIONS {
+        let (size, off) = section(type_).map_or((0, 0), |item| (item.2, item.3));
+        builder.set_section(type_, size, off);
+    }
+    builder.set("data_size", OFF_DATA_SIZE, file_size - data_off);
+    builder.set("data_off", OFF_DATA_OFF, data_off);
+
+    let strings = section(TYPE_STRING_ID_ITEM).map_or((0, 0), |item| (item.2, item.3));
+    let types = section(TYPE_TYPE_ID_ITEM).map_or((0, 0), |item| (item.2, item.3));
+    let string_ids = image.read_u32s(strings.1, strings.0.min(ID_SAMPLE)).unwrap_or_default();
+    let sample = read_strings(image, &string_ids, data_off, file_size);
+    let checks = [
+        ("magic", &header[0..4] == b"dex\n"),
+        ("header_agrees", builder.agrees()),
+        ("sections_disjoint", disjoint),
+        ("extent_known", extent_known),
+        ("string_data_valid", sample.as_ref().is_some_and(|s| s.len() == strings.0.min(ID_SAMPLE))),
+        ("strings_sorted", sample.as_deref().is_some_and(is_sorted)),
+        ("type_ids_valid", count_type_ids(image, types.1, types.0.min(ID_SAMPLE), strings.1, strings.0) == types.0.min(ID_SAMPLE)),
+    ];
+    builder.finish(ReconstructionMethod::MapList, file_size, &checks)
+}
+
+/// The string_ids and type_ids tables, when no map_list is left.
+fn from_string_ids(image: &Image, header: &[u8; HEADER_SIZE]) -> Option<Reconstruction> {
+    let mut builder = HeaderBuilder::new(header);
+    let header_size = match builder.old(OFF_HEADER_SIZE) as usize {
+        HEADER_SIZE_V41 => HEADER_SIZE_V41,
+        _ => HEADER_SIZE,
+    };
+
+    // String data is laid out in id order, so valid ids are strictly ascending
+    let mut strings = Vec::new();
+    let mut data_end = header_size;
+    let mut first = None;
+    let mut previous = 0;
+    while let Some(ids) = image.read_u32s(header_size + strings.len() * 4, 1) {
+        let offset = ids[0] as usize;
+        if offset <= previous.max(header_size) {
+            break;
+        }
+        let Some((string, end)) = image.read_string(offset) else { break };
+        strings.push(string);
+        data_end = data_end.max(end);
+        first.get_or_insert(offset);
+        previous = offset;
+    }
+    // String data follows every id table
+    let string_count = strings.len();
+    let types_off = header_size + string_count * 4;
+    if string_count < MIN_STRING_IDS || first.is_none_or(|first| first < types_off) {
+        return None;
+    }
+
+    // type_ids are sorted string indices of descriptors
+    let mut type_count = 0;
+    let mut last_index = None;
+    while let Some(ids) = image.read_u32s(types_off + type_count * 4, 1) {
+        let index = ids[0] as usize;
+        let is_descriptor = strings.get(index).and_then(|s| s.first()).is_some_and(|c| b"VZBSCIJFDL[".contains(c));
+        if !is_descriptor || last_index.is_some_and(|last| index <= last) {
+            break;
+        }
+        last_index = Some(index);
+        type_count += 1;
+    }
+
+    // The furthest string is only a lower bound, keep a larger file_size that still fits
+    let old_file_size = builder.old(OFF_FILE_SIZE) as usize;
+    let file_size = if old_file_size >= data_end.next_multiple_of(4) && old_file_size <= image.extent {
+        old_file_size
+    } else {
+        data_end.next_multiple_of(4).min(image.extent)
+    };
+
+    builder.set("file_size", OFF_FILE_SIZE, file_size);
+    builder.set("header_size", OFF_HEADER_SIZE, header_size);
+    builder.set("endian_tag", OFF_ENDIAN_TAG, ENDIAN_CONSTANT as usize);
+    builder.set_section(TYPE_STRING_ID_ITEM, string_count, header_size);
+    builder.set_section(TYPE_TYPE_ID_ITEM, type_count, if type_count > 0 { types_off } else { 0 });
+
+    let checks = [
+        ("magic", &header[0..4] == b"dex\n"),
+        ("header_agrees", builder.agrees()),
+        ("strings_sorted", is_sorted(&strings)),
+        ("type_ids_valid", type_count > 0),
+    ];
+    Some(builder.finish(ReconstructionMethod::StringIds, file_size, &checks))
+}
+
+/// The strings at `ids`, if each is valid and lies in `data_off..file_size`.
+fn read_strings(image: &Image, ids: &[u32], data_off: usize, file_size: usize) -> Option<Vec<Vec<u8>>> {
+    ids.iter()
+        .map(|&offset| {
+            let offset = offset as usize;
+            if offset < data_off || offset >= file_size {
+                return None;
+            }
+            image.read_string(offset).filter(|(_, end)| *end <= file_size).map(|(string, _)| string)
+        })
+        .collect()
+}
+
+/// How many of the first `count` type_ids at `types_off` are ascending indices of descriptor strings.
+fn count_type_ids(image: &Image, types_off: usize, count: usize, strings_off: usize, string_count: usize) -> usize {
+    let Some(indices) = image.read_u32s(types_off, count) else {
+        return 0;
+    };
+    let mut valid = 0;
+    for (i, &index) in indices.iter().enumerate() {
+        let index = index as usize;
+        if index >= string_count || (i > 0 && index <= indices[i - 1] as usize) {
+            break;
+        }
+        let descriptor = image
+            .read_u32s(strings_off + index * 4, 1)
+            .and_then(|id| image.read_string(id[0] as usize))
+            .is_some_and(|(s, _)| s.first().is_some_and(|c| b"VZBSCIJFDL[".contains(c)));
+        if !descriptor {
+            break;
+        }
+        valid += 1;
+    }
+    valid
+}
+
+/// Dex sorts strings by UTF-16 code units, the same order as their MUTF-8 bytes.
+fn is_sorted(strings: &[Vec<u8>]) -> bool {
+    strings.windows(2).all(|pair| pair[0].cmp(&pair[1]) == Ordering::Less)
+}
+
+/// `(value, bytes used)`
+fn read_uleb128(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0u32;
+    for (i, &byte) in bytes.iter().enumerate().take(5) {
+        value |= ((byte & 0x7f) as u32) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Length of the MUTF-8 string in `bytes` up to its NUL, if it holds exactly `utf16_size` units.
+fn mutf8_len(bytes: &[u8], utf16_size: usize) -> Option<usize> {
+    let mut i = 0;
+    let mut units = 0;
+    while let Some(&byte) = bytes.get(i) {
+        let len = match byte {
+            0 => return (units == utf16_size).then_some(i),
+            0x01..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            _ => return None,
+        };
+        if !bytes.get(i + 1..i + len)?.iter().all(|b| b & 0xc0 == 0x80) {
+            return None;
+        }
+        i += len;
+        units += 1;
+        if units > utf16_size {
+            return None;
+        }
+    }
+    None
+}
@@ -0,0 +1,171 @@
+// dex_repair.rs
+
+use super::dex_parser::dex_structs;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::mem::size_of;
+
+// --- Constants ---
+const OFF_CHECKSUM: usize = 0x08;
+const OFF_SIGNATURE: usize = 0x0C;
+const OFF_FILE_SIZE: usize = 0x20;
+const SIGNATURE_SIZE: usize = 20;
+
+const HEADER_SIZE_V41: u32 = 0x78; // v41 adds container_size_ / header_offset_
+const MAP_ITEM_SIZE: usize = size_of::<dex_structs::MapItem>();
+const MAX_MAP_ITEMS: usize = 1000;
+
+const KNOWN_VERSIONS: &[&[u8; 3]] = &[b"035", b"037", b"038", b"039", b"040", b"041"];
+
+/// A single header field that was rewritten before the dex was saved.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeaderFix {
+    pub field: &'static str,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+/// Outcome of `repair_header` for one dumped dex.
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// Version written into the magic, e.g. "035", or "cdex" if untouched.
+    pub version: String,
+    pub fixes: Vec<HeaderFix>,
+}
+
+/// Restores the magic and recomputes `checksum` / `signature` of a dumped dex in place,
+/// so tools like jadx, baksmali and dexdump accept it.
+/// Compact dex is left untouched.
+pub fn repair_header(data: &mut [u8]) -> RepairReport {
+    let mut report = RepairReport::default();
+    if data.len() < size_of::<dex_structs::Header>() {
+        report.version = "unknown".to_string();
+        return report;
+    }
+
+    if &data[0..4] == b"cdex" {
+        report.version = "cdex".to_string();
+        return report;
+    }
+
+    let header: dex_structs::Header =
+        bytemuck::pod_read_unaligned(&data[..size_of::<dex_structs::Header>()]);
+
+    // 1. Magic
+    let version = infer_version(data, &header);
+    let mut magic = [0u8; 8];
+    magic[0..4].copy_from_slice(b"dex\n");
+    magic[4..7].copy_from_slice(version);
+    if data[0..8] != magic {
+        report.fixes.push(HeaderFix {
+            field: "magic",
+            old_value: escape_bytes(&data[0..8]),
+            new_value: escape_bytes(&magic),
+        });
+        data[0..8].copy_from_slice(&magic);
+    }
+    report.version = String::from_utf8_lossy(version).to_string();
+
+    // Only hash what the header claims, never past what we actually hold
+    let end = std::cmp::min(header.file_size as usize, data.len());
+    if end <= OFF_FILE_SIZE {
+        return report;
+    }
+
+    // 2. Signature (SHA-1 of everything after the signature field)
+    let signature: [u8; SIGNATURE_SIZE] = Sha1::digest(&data[OFF_FILE_SIZE..end]).into();
+    let sig_range = OFF_SIGNATURE..OFF_SIGNATURE + SIGNATURE_SIZE;
+    if data[sig_range.clone()] != signature {
+        report.fixes.push(HeaderFix {
+            field: "signature",
+            old_value: to_hex(&data[sig_range.clone()]),
+            new_value: to_hex(&signature),
+        });
+        data[sig_range].copy_from_slice(&signature);
+    }
+
+    // 3. Checksum (Adler-32 of everything after the checksum field, signature included)
+    let checksum = adler32(&data[OFF_SIGNATURE..end]);
+    if header.checksum != checksum {
+        report.fixes.push(HeaderFix {
+            field: "checksum",
+            old_value: format!("0x{:08x}", header.checksum),
+            new_value: format!("0x{:08x}", checksum),
+        });
+        data[OFF_CHECKSUM..OFF_CHECKSUM + 4].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    report
+}
+
+/// Keeps the version digits if the packer left them intact, otherwise
+/// picks the lowest version that can describe the header/map layout.
+fn infer_version(data: &[u8], header: &dex_structs::Header) -> &'static [u8; 3] {
+    if data[7] == 0
+        && let Some(known) = KNOWN_VERSIONS.iter().find(|v| data[4..7] == v[..])
+    {
+        return known;
+    }
+
+    if header.header_size >= HEADER_SIZE_V41 {
+        return b"041";
+    }
+
+    let map_types = read_map_item_types(data, header.map_off as usize);
+    if map_types
+        .iter()
+        .any(|t| *t == dex_structs::TYPE_CALL_SITE_ID_ITEM || *t == dex_structs::TYPE_METHOD_HANDLE_ITEM)
+    {
+        return b"038";
+    }
+
+    b"035"
+}
+
+/// Reads the `type_` of every `MapItem`, bounds-checked against `data`.
+fn read_map_item_types(data: &[u8], map_off: usize) -> Vec<u16> {
+    let mut types = Vec::new();
+    let Some(size_bytes) = data.get(map_off..map_off + 4) else {
+        return types;
+    };
+    let count = u32::from_le_bytes(size_bytes.try_into().unwrap()) as usize;
+    if count > MAX_MAP_ITEMS {
+        return types;
+    }
+
+    for i in 0..count {
+        let item_off = map_off + 4 + i * MAP_ITEM_SIZE;
+        match data.get(item_off..item_off + MAP_ITEM_SIZE) {
+            Some(bytes) => {
+                let item: dex_structs::MapItem = bytemuck::pod_read_unaligned(bytes);
+                types.push(item.type_);
+            }
+            None => break,
+        }
+    }
+    types
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    const NMAX: usize = 5552; // largest n such that the sums cannot overflow u32
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(NMAX) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn escape_bytes(bytes: &[u8]) -> String {
+    bytes.escape_ascii().to_string()
+}
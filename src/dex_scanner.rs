@@ -1,7 +1,16 @@
-use proc_maps::{get_process_maps, MapRange};
+use super::dex_dumper::page_size;
+use super::dex_reconstruct::{self, Reconstruction};
+use super::maps::{get_process_maps, MapInfo, MapRange};
+use super::memory_reader::MemoryReader;
+use log::{error, info};
+use serde::Serialize;
 use std::cmp::Ordering;
-use std::mem::size_of;
+use std::collections::HashSet;
 use std::io;
+use std::mem::size_of;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
 pub struct DexFileResult {
@@ -9,12 +18,187 @@ pub struct DexFileResult {
     pub size: usize,
     pub version: String,
     pub source: String,
+    /// What backs the map the dex lies in
+    pub classification: DexClassification,
+    /// The map the dex lies in
+    pub map: Option<MapInfo>,
+    /// For pointer scan hits, where the pointer was found
+    pub referenced_from: Option<PointerRef>,
+    /// The header was wiped and rebuilt from the sections, see `ScanOptions::reconstruct_headers`
+    pub reconstruction: Option<Reconstruction>,
+}
+
+/// A word in memory holding the address of a dex.
+#[derive(Debug, Clone, Serialize)]
+pub struct PointerRef {
+    /// Address of the word itself
+    pub address: String,
+    /// The word as found, tag bits included
+    pub value: String,
+    /// The map the word lies in, e.g. the heap of the native structure pointing at the dex
+    pub map: Option<MapInfo>,
+}
+
+/// Where a dex lives, by the map that holds it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DexClassification {
+    /// `/system/framework`, the other partitions' `framework` dirs, or a boot image file
+    Framework,
+    /// A jar or image under `/apex/`
+    Apex,
+    /// `[anon:dalvik-...]`, e.g. dex ART extracted from an apk into memory
+    DalvikAnon,
+    /// An apk, or its odex/vdex, installed under `/data/app/`
+    AppApk,
+    /// Any other file
+    OtherFile,
+    /// No backing file: heap, `memfd:`, ashmem, other `[anon:...]`
+    Anonymous,
+}
+
+impl DexClassification {
+    /// Framework and apex dex, the same on every device of a build.
+    pub fn is_system(self) -> bool {
+        matches!(self, Self::Framework | Self::Apex)
+    }
+}
+
+const FRAMEWORK_DIRS: [&str; 5] = [
+    "/system/framework/",
+    "/system_ext/framework/",
+    "/product/framework/",
+    "/vendor/framework/",
+    "/odm/framework/",
+];
+
+/// Classifies a dex by `map`, the mapping holding its first byte.
+pub fn classify_map(map: Option<&MapRange>) -> DexClassification {
+    let Some(path) = map.and_then(|m| m.filename()) else {
+        return DexClassification::Anonymous;
+    };
+    let path = path.to_string_lossy();
+
+    if path.starts_with("[anon:dalvik-") {
+        DexClassification::DalvikAnon
+    } else if path.starts_with('[') || path.starts_with("/memfd:") || path.starts_with("/dev/") {
+        DexClassification::Anonymous
+    } else if path.starts_with("/apex/") {
+        DexClassification::Apex
+    } else if FRAMEWORK_DIRS.iter().any(|dir| path.starts_with(dir)) || is_boot_classpath_file(&path, &[]) {
+        DexClassification::Framework
+    } else if path.starts_with("/data/app/") {
+        DexClassification::AppApk
+    } else {
+        DexClassification::OtherFile
+    }
+}
+
+/// What `scan_memory` looks for and where.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Look for `dex\n` / `cdex` magic
+    pub magic_scan: bool,
+    /// Follow every pointer-sized word, finds dex with a wiped magic
+    pub deep_search: bool,
+    /// Maps whose path starts with any of these are not scanned
+    pub skip_prefixes: Vec<String>,
+    /// Candidates whose header claims more than this are rejected
+    pub max_dex_size: usize,
+    /// Drop dex backed by a `BOOTCLASSPATH` jar or the boot image, present in every process
+    pub skip_boot_classpath: bool,
+    /// Drop dex classified as framework or apex
+    pub skip_system_dex: bool,
+    /// Bits cleared from every word before the pointer scan looks it up,
+    /// `DEFAULT_POINTER_TAG_MASK` for the arm64 TBI byte (which holds the MTE tag)
+    pub pointer_tag_mask: u64,
+    /// Pointer size of the scanned process, which may differ from ours on the host
+    pub pointer_width: PointerWidth,
+    /// Worker threads, 0 for half the CPUs
+    pub threads: usize,
+    /// Share of one CPU each worker may use, 1..=100; workers sleep between chunks to stay below it
+    pub cpu_percent: u32,
+    /// Rebuild the header of candidates that fail verification from their map_list or id tables.
+    /// Magic hits always qualify, pointer targets only with an intact `dex\n` magic or endian tag
+    pub reconstruct_headers: bool,
+}
+
+impl ScanOptions {
+    /// `threads`, or half the CPUs when it is 0.
+    pub fn worker_count(&self) -> usize {
+        match self.threads {
+            0 => std::thread::available_parallelism().map(|n| n.get() / 2).unwrap_or(1).max(1),
+            n => n,
+        }
+    }
+}
+
+/// Word size of an address space, the stride of the pointer scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PointerWidth {
+    Bits32,
+    Bits64,
+}
+
+impl PointerWidth {
+    /// Ours.
+    pub fn native() -> Self {
+        if size_of::<usize>() == 8 { Self::Bits64 } else { Self::Bits32 }
+    }
+
+    pub fn bytes(self) -> usize {
+        match self {
+            Self::Bits32 => 4,
+            Self::Bits64 => 8,
+        }
+    }
+
+    /// From `EI_CLASS` of an ELF identification.
+    pub fn from_elf_ident(ident: &[u8]) -> Option<Self> {
+        if ident.get(0..4)? != b"\x7fELF" {
+            return None;
+        }
+        match ident.get(4)? {
+            1 => Some(Self::Bits32),
+            2 => Some(Self::Bits64),
+            _ => None,
+        }
+    }
+
+    /// From the executable of `pid`: `app_process32` for a 32-bit app.
+    pub fn of_process(pid: libc::pid_t) -> io::Result<Self> {
+        let mut ident = [0u8; 5];
+        std::io::Read::read_exact(&mut std::fs::File::open(format!("/proc/{}/exe", pid))?, &mut ident)?;
+        Self::from_elf_ident(&ident).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an ELF executable"))
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            magic_scan: true,
+            deep_search: true,
+            skip_prefixes: Vec::new(),
+            max_dex_size: MAX_DEX_SIZE,
+            skip_boot_classpath: false,
+            skip_system_dex: true,
+            pointer_tag_mask: DEFAULT_POINTER_TAG_MASK,
+            pointer_width: PointerWidth::native(),
+            threads: 0,
+            cpu_percent: 100,
+            reconstruct_headers: false,
+        }
+    }
 }
 
 // --- Constants ---
 const CHUNK_SIZE: usize = 1024 * 1024; // 1MB chunk
-const MAX_DEX_SIZE: usize = 200 * 1024 * 1024; // 200MB limit
+const SEGMENT_SIZE: usize = 16 * CHUNK_SIZE; // work item of a scan worker
+pub const MAX_DEX_SIZE: usize = 200 * 1024 * 1024; // 200MB limit
 const MIN_DEX_SIZE: usize = 0x70;
+const HEADER_SIZE: usize = 0x70;
+/// arm64 Top Byte Ignore: Scudo (Android 11+) and MTE put a tag in bits 56..63 of heap pointers
+pub const DEFAULT_POINTER_TAG_MASK: u64 = 0xff00_0000_0000_0000;
 
 /* 
   struct Header {                     type     size   offset
@@ -55,44 +239,167 @@ const OFF_STRING_IDS_OFF: usize = 0x3C;
 const ENDIAN_CONSTANT: u32 = 0x12345678;
 const REVERSE_ENDIAN_CONSTANT: u32 = 0x78563412;
 
-pub fn safe_read_memory(pid: libc::pid_t, addr: usize, buf: &mut [u8]) -> Result<usize, io::Error> {
-    let local_iov = libc::iovec {
-        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
-        iov_len: buf.len(),
-    };
-    let remote_iov = libc::iovec {
-        iov_base: addr as *mut libc::c_void,
-        iov_len: buf.len(),
-    };
+/// Returns the mappings of `pid` sorted by start address, as `find_map_binary` expects.
+pub fn get_sorted_maps(pid: libc::pid_t) -> Result<Vec<MapRange>, io::Error> {
+    let mut ranges = get_process_maps(pid)?;
+    ranges.sort_by_key(|r| r.start());
+    Ok(ranges)
+}
 
-    let nread = unsafe {
-        libc::process_vm_readv(
-            pid,
-            &local_iov,
-            1,
-            &remote_iov,
-            1,
-            0
-        )
-    };
+/// Counters of one `scan_memory` run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScanStats {
+    /// Words of the pointer scan that point into a mapped range
+    pub pointer_candidates: usize,
+    /// Candidates in an unreadable map, or a page that already failed to read
+    pub unreadable_targets: usize,
+    /// Candidates already verified or rejected earlier in this scan
+    pub cache_hits: usize,
+    /// Headers read and checked, magic scan included
+    pub verifications: usize,
+    /// Dex found, before deduplication
+    pub hits: usize,
+    /// Hits whose wiped header was rebuilt
+    pub reconstructed: usize,
+    /// Worker threads the scan ran on
+    pub workers: usize,
+    /// Unreadable pages inside readable maps, skipped one by one
+    pub skipped_pages: usize,
+    /// Maps that had skipped pages, with their count
+    pub skipped_by_map: Vec<SkippedPages>,
+}
 
-    if nread == -1 {
-        // no panic when EFAULT (Bad address)
-        return Err(io::Error::last_os_error());
+/// Pages of one map that could not be read.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedPages {
+    #[serde(skip)]
+    start: usize,
+    pub map: MapInfo,
+    pub pages: usize,
+}
+
+impl ScanStats {
+    fn add_skipped(&mut self, map: &MapRange, pages: usize) {
+        self.skipped_pages += pages;
+        match self.skipped_by_map.iter_mut().find(|s| s.start == map.start()) {
+            Some(skipped) => skipped.pages += pages,
+            None => self.skipped_by_map.push(SkippedPages { start: map.start(), map: MapInfo::from(map), pages }),
+        }
     }
+}
 
-    Ok(nread as usize)
+impl std::ops::AddAssign for ScanStats {
+    fn add_assign(&mut self, other: Self) {
+        self.pointer_candidates += other.pointer_candidates;
+        self.unreadable_targets += other.unreadable_targets;
+        self.cache_hits += other.cache_hits;
+        self.verifications += other.verifications;
+        self.hits += other.hits;
+        self.reconstructed += other.reconstructed;
+        self.workers += other.workers;
+        self.skipped_pages += other.skipped_pages;
+        for skipped in other.skipped_by_map {
+            match self.skipped_by_map.iter_mut().find(|s| s.start == skipped.start) {
+                Some(mine) => mine.pages += skipped.pages,
+                None => self.skipped_by_map.push(skipped),
+            }
+        }
+    }
 }
 
-pub fn scan_memory(deep_search: bool) -> Result<Vec<DexFileResult>, std::io::Error> {
-    let pid = std::process::id() as libc::pid_t;
-    
-    let mut ranges = get_process_maps(pid)?;
-    ranges.sort_by(|a, b| a.start().cmp(&b.start()));
+/// Pointer targets checked and pages found unreadable by any worker of one scan.
+#[derive(Default)]
+struct SharedTargets {
+    checked: HashSet<usize>,
+    bad_pages: HashSet<usize>,
+}
 
-    let mut results = Vec::new();
+/// Pointer targets already checked in this scan. The same heap address is referenced
+/// thousands of times, each verification costs at least two reads. Every worker keeps its
+/// own copy in front of the shared sets, so repeats are answered without taking the lock.
+struct VerifyCache<'a> {
+    checked: HashSet<usize>,
+    bad_pages: HashSet<usize>,
+    shared: &'a Mutex<SharedTargets>,
+    page_size: usize,
+    stats: ScanStats,
+}
+
+impl<'a> VerifyCache<'a> {
+    fn new(shared: &'a Mutex<SharedTargets>) -> Self {
+        Self { checked: HashSet::new(), bad_pages: HashSet::new(), shared, page_size: page_size(), stats: ScanStats::default() }
+    }
+
+    fn mark_bad_page(&mut self, page: usize) {
+        self.bad_pages.insert(page);
+        self.shared.lock().unwrap_or_else(|e| e.into_inner()).bad_pages.insert(page);
+    }
+
+    /// Verifies the pointer target `addr`, which lies in `target_map`, unless it was seen before.
+    fn verify_pointer(
+        &mut self,
+        reader: &dyn MemoryReader,
+        addr: usize,
+        target_map: &MapRange,
+        all_ranges: &[MapRange],
+        options: &ScanOptions,
+    ) -> Option<DexFileResult> {
+        self.stats.pointer_candidates += 1;
+        let page = addr / self.page_size;
+        if !target_map.is_read() || self.bad_pages.contains(&page) {
+            self.stats.unreadable_targets += 1;
+            return None;
+        }
+        if !self.checked.insert(addr) {
+            self.stats.cache_hits += 1;
+            return None;
+        }
+        {
+            // Another worker may have been here first
+            let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+            if shared.bad_pages.contains(&page) {
+                self.bad_pages.insert(page);
+                self.stats.unreadable_targets += 1;
+                return None;
+            }
+            if !shared.checked.insert(addr) {
+                self.stats.cache_hits += 1;
+                return None;
+            }
+        }
+
+        self.stats.verifications += 1;
+        let Some(header) = read_header(reader, addr) else {
+            // Only blame this page if the header did not reach into the next one
+            if (addr + HEADER_SIZE - 1) / self.page_size == page {
+                self.mark_bad_page(page);
+            }
+            return None;
+        };
+        let mut result = verify_header(reader, addr, &header, all_ranges, true, options.max_dex_size);
+        if result.is_none() && options.reconstruct_headers && has_anchor(&header) {
+            result = reconstruct_candidate(reader, addr, &header, all_ranges, options.max_dex_size);
+            self.stats.reconstructed += result.is_some() as usize;
+        }
+        self.stats.hits += result.is_some() as usize;
+        result
+    }
+}
+
+/// Scans every readable range in `ranges` (sorted by start address) through `reader`.
+pub fn scan_memory(reader: &dyn MemoryReader, ranges: &[MapRange], options: &ScanOptions) -> Vec<DexFileResult> {
+    scan_memory_with_stats(reader, ranges, options).0
+}
+
+/// `scan_memory`, also returning how much verification work the scan did.
+///
+/// Ranges are cut into segments that `ScanOptions::worker_count` threads take from a shared queue,
+/// each with its own buffer. A pointer target is verified by one worker only.
+pub fn scan_memory_with_stats(reader: &dyn MemoryReader, ranges: &[MapRange], options: &ScanOptions) -> (Vec<DexFileResult>, ScanStats) {
+    let boot_classpath = if options.skip_boot_classpath { boot_classpath() } else { Vec::new() };
 
-    for range in &ranges {
+    let mut segments = Vec::new();
+    for range in ranges {
         if !range.is_read() { continue; }
         
         if let Some(path) = range.filename() {
@@ -100,66 +407,220 @@ pub fn scan_memory(deep_search: bool) -> Result<Vec<DexFileResult>, std::io::Err
             if s.starts_with("/dev/") && !s.contains("ashmem") && !s.contains("zero") {
                 continue;
             }
+            if options.skip_prefixes.iter().any(|prefix| s.starts_with(prefix.as_str())) {
+                continue;
+            }
+            if options.skip_boot_classpath && is_boot_classpath_file(&s, &boot_classpath) {
+                continue;
+            }
+        }
+        if options.skip_system_dex && classify_map(Some(range)).is_system() {
+            continue;
         }
 
-        scan_map_chunked(pid, range, &ranges, &mut results, deep_search);
+        for offset in (0..range.size()).step_by(SEGMENT_SIZE) {
+            segments.push(Segment { range, offset, len: std::cmp::min(SEGMENT_SIZE, range.size() - offset) });
+        }
     }
 
-    results.sort_by_key(|r| r.addr);
+    let workers = options.worker_count().min(segments.len()).max(1);
+    let next_segment = AtomicUsize::new(0);
+    let shared = Mutex::new(SharedTargets::default());
+    let outputs: Vec<(Vec<DexFileResult>, ScanStats)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| scope.spawn(|| scan_worker(reader, &segments, &next_segment, &shared, ranges, options)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle.join().unwrap_or_else(|_| {
+                    error!("Scan worker panicked, its results are lost");
+                    Default::default()
+                })
+            })
+            .collect()
+    });
+
+    let mut results = Vec::new();
+    let mut stats = ScanStats::default();
+    for (worker_results, worker_stats) in outputs {
+        results.extend(worker_results);
+        stats += worker_stats;
+    }
+
+    // Which worker finds a dex first varies, keep the output stable: magic hits first
+    results.sort_by(|a, b| a.addr.cmp(&b.addr).then_with(|| a.source.cmp(&b.source)));
     results.dedup_by_key(|r| r.addr);
-    
-    Ok(results)
+
+    // Pointer hits from other maps can still land in a skipped map
+    if options.skip_system_dex {
+        results.retain(|r| !r.classification.is_system());
+    }
+    if options.skip_boot_classpath {
+        results.retain(|r| {
+            let path = find_map_binary(ranges, r.addr).and_then(|m| m.filename());
+            !path.is_some_and(|p| is_boot_classpath_file(&p.to_string_lossy(), &boot_classpath))
+        });
+    }
+
+    stats.skipped_by_map.sort_by_key(|s| s.start);
+    for skipped in &stats.skipped_by_map {
+        info!(
+            "Skipped {} unreadable page(s) in {}-{} {}",
+            skipped.pages, skipped.map.start, skipped.map.end, skipped.map.path.as_deref().unwrap_or("")
+        );
+    }
+    info!(
+        "Scan: {} pointer candidates, {} unreadable, {} cache hits, {} verifications, {} hits ({} rebuilt) on {} workers, {} pages skipped",
+        stats.pointer_candidates, stats.unreadable_targets, stats.cache_hits, stats.verifications, stats.hits, stats.reconstructed, stats.workers, stats.skipped_pages
+    );
+    (results, stats)
+}
+
+/// Jars of `BOOTCLASSPATH` in our own environment, the same in every process forked from zygote.
+pub fn boot_classpath() -> Vec<String> {
+    std::env::var("BOOTCLASSPATH")
+        .map(|value| value.split(':').filter(|p| !p.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
 }
 
-fn scan_map_chunked(
-    pid: libc::pid_t,
-    range: &MapRange,
+/// A jar of `boot_classpath`, or a boot image file (`boot*.vdex`, `boot*.oat`, `boot*.art`)
+/// holding their dex.
+fn is_boot_classpath_file(path: &str, boot_classpath: &[String]) -> bool {
+    if boot_classpath.iter().any(|jar| jar == path) {
+        return true;
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.starts_with("boot") && [".vdex", ".oat", ".art"].iter().any(|ext| name.ends_with(ext))
+}
+
+/// `len` bytes of `range` from `offset`, the unit of work of the scan workers.
+struct Segment<'a> {
+    range: &'a MapRange,
+    offset: usize,
+    len: usize,
+}
+
+/// Scans segments from the shared queue until it is empty.
+fn scan_worker(
+    reader: &dyn MemoryReader,
+    segments: &[Segment],
+    next_segment: &AtomicUsize,
+    shared: &Mutex<SharedTargets>,
+    all_ranges: &[MapRange],
+    options: &ScanOptions
+) -> (Vec<DexFileResult>, ScanStats) {
+    let mut results = Vec::new();
+    let mut cache = VerifyCache::new(shared);
+    let mut buf = vec![0u8; CHUNK_SIZE];
+
+    while let Some(segment) = segments.get(next_segment.fetch_add(1, AtomicOrdering::Relaxed)) {
+        scan_segment_chunked(reader, segment, all_ranges, &mut buf, &mut results, options, &mut cache);
+    }
+
+    cache.stats.workers = 1;
+    (results, cache.stats)
+}
+
+fn scan_segment_chunked(
+    reader: &dyn MemoryReader,
+    segment: &Segment,
     all_ranges: &[MapRange],
+    buf: &mut [u8],
     results: &mut Vec<DexFileResult>,
-    deep_search: bool
+    options: &ScanOptions,
+    cache: &mut VerifyCache
 ) {
     let mut offset = 0;
-    let map_size = range.size();
-    let start_addr = range.start();
+    let start_addr = segment.range.start() + segment.offset;
 
-    let mut buf = vec![0u8; CHUNK_SIZE];
-
-    while offset < map_size {
-        let to_read = std::cmp::min(CHUNK_SIZE, map_size - offset);
+    while offset < segment.len {
+        let to_read = std::cmp::min(CHUNK_SIZE, segment.len - offset);
         let current_chunk_addr = start_addr + offset;
-        
-        match safe_read_memory(pid, current_chunk_addr, &mut buf[..to_read]) {
-            Ok(n) if n > 0 => {
-                let valid_buf = &buf[..n];
-                scan_buffer_magic(valid_buf, current_chunk_addr, pid, all_ranges, results);
-
-                if deep_search && size_of::<usize>() == 8 {
-                    scan_buffer_pointers(valid_buf, current_chunk_addr, pid, all_ranges, results);
-                }
+        let started = Instant::now();
+
+        // Guard pages (PROT_NONE) in the middle of a map only cost their own page, zero-filled
+        let skipped = read_bisect(reader, current_chunk_addr, &mut buf[..to_read], cache);
+        if skipped > 0 {
+            cache.stats.add_skipped(segment.range, skipped);
+        }
+        if skipped * cache.page_size < to_read {
+            // Chunks start 4-aligned and so do dex headers and pointers, no hit straddles two chunks
+            let valid_buf = &buf[..to_read];
+            if options.magic_scan {
+                scan_buffer_magic(valid_buf, current_chunk_addr, reader, all_ranges, results, options, cache);
+            }
+
+            if options.deep_search {
+                scan_buffer_pointers(valid_buf, current_chunk_addr, reader, all_ranges, results, options, cache);
             }
-            _ => break, 
         }
         offset += to_read;
+
+        // Busy for `elapsed`, so idle long enough that busy / (busy + idle) == cpu_percent
+        let cpu_percent = options.cpu_percent.clamp(1, 100);
+        if cpu_percent < 100 {
+            std::thread::sleep(started.elapsed() * (100 - cpu_percent) / cpu_percent);
+        }
+    }
+}
+
+/// Fills `buf` from `addr`. A failed read is split in two at a page boundary until single
+/// pages fail; those are zero-filled, remembered as bad and counted in the return value.
+fn read_bisect(reader: &dyn MemoryReader, addr: usize, buf: &mut [u8], cache: &mut VerifyCache) -> usize {
+    if buf.is_empty() {
+        return 0;
+    }
+    let page = cache.page_size;
+    match reader.read_memory(addr, buf) {
+        Ok(n) if n >= buf.len() => 0,
+        // Stopped at the first unreadable byte, the rest may still be readable
+        Ok(n) if n > 0 => read_bisect(reader, addr + n, &mut buf[n..], cache),
+        _ => {
+            let end = addr + buf.len();
+            let first_page = addr / page;
+            if (end - 1) / page == first_page {
+                buf.fill(0);
+                cache.mark_bad_page(first_page);
+                return 1;
+            }
+            // Page boundary closest to the middle, strictly inside the buffer
+            let mid = ((addr + buf.len() / 2) / page * page).max((first_page + 1) * page);
+            let (left, right) = buf.split_at_mut(mid - addr);
+            read_bisect(reader, addr, left, cache) + read_bisect(reader, mid, right, cache)
+        }
     }
 }
 
 fn scan_buffer_magic(
     buf: &[u8],
     base_addr: usize,
-    pid: libc::pid_t,
+    reader: &dyn MemoryReader,
     all_ranges: &[MapRange],
-    results: &mut Vec<DexFileResult>
+    results: &mut Vec<DexFileResult>,
+    options: &ScanOptions,
+    cache: &mut VerifyCache
 ) {
     let mut i = 0;
-    while i + 8 <= buf.len() {
+    while i + 4 <= buf.len() {
         let is_dex = &buf[i..i+4] == b"dex\n";
         let is_cdex = &buf[i..i+4] == b"cdex";
 
         if is_dex || is_cdex {
             let candidate_addr = base_addr + i;
-            if let Some(res) = verify_and_parse(pid, candidate_addr, all_ranges, false) {
+            cache.stats.verifications += 1;
+            let header = read_header(reader, candidate_addr);
+            let mut result = header.and_then(|h| verify_header(reader, candidate_addr, &h, all_ranges, false, options.max_dex_size));
+            if result.is_none() && options.reconstruct_headers && is_dex
+                && let Some(header) = header
+            {
+                result = reconstruct_candidate(reader, candidate_addr, &header, all_ranges, options.max_dex_size);
+                cache.stats.reconstructed += result.is_some() as usize;
+            }
+            if let Some(res) = result {
                 let mut r = res;
                 r.source = "MagicScan".to_string();
+                cache.stats.hits += 1;
                 results.push(r);
             }
         }
@@ -169,26 +630,45 @@ fn scan_buffer_magic(
 
 fn scan_buffer_pointers(
     buf: &[u8],
-    _base_addr: usize,
-    pid: libc::pid_t,
+    base_addr: usize,
+    reader: &dyn MemoryReader,
     all_ranges: &[MapRange],
-    results: &mut Vec<DexFileResult>
+    results: &mut Vec<DexFileResult>,
+    options: &ScanOptions,
+    cache: &mut VerifyCache
 ) {
-    let step = 8;
+    let step = options.pointer_width.bytes();
     let mut i = 0;
-    while i + 8 <= buf.len() {
-        let ptr_val = u64::from_le_bytes(buf[i..i+8].try_into().unwrap()) as usize;
+    while i + step <= buf.len() {
+        let word = match options.pointer_width {
+            PointerWidth::Bits64 => u64::from_le_bytes(buf[i..i+8].try_into().unwrap()),
+            PointerWidth::Bits32 => u32::from_le_bytes(buf[i..i+4].try_into().unwrap()) as u64,
+        };
+        // 32-bit address spaces have no tag byte
+        let address = match options.pointer_width {
+            PointerWidth::Bits64 => strip_pointer_tag(word, options.pointer_tag_mask),
+            PointerWidth::Bits32 => word,
+        };
+        let Ok(ptr_val) = usize::try_from(address) else {
+            i += step;
+            continue;
+        };
 
         if ptr_val == 0 || ptr_val % 4 != 0 {
             i += step;
             continue;
         }
 
-        if let Some(_target_map) = find_map_binary(all_ranges, ptr_val) {
+        if let Some(target_map) = find_map_binary(all_ranges, ptr_val) {
             // valid pointer to memory -> verify
-            if let Some(res) = verify_and_parse(pid, ptr_val, all_ranges, true) {
+            if let Some(res) = cache.verify_pointer(reader, ptr_val, target_map, all_ranges, options) {
                 let mut r = res;
                 r.source = "PointerScan".to_string();
+                r.referenced_from = Some(PointerRef {
+                    address: format!("0x{:x}", base_addr + i),
+                    value: format!("0x{:x}", word),
+                    map: find_map_binary(all_ranges, base_addr + i).map(MapInfo::from),
+                });
                 results.push(r);
             }
         }
@@ -196,16 +676,25 @@ fn scan_buffer_pointers(
     }
 }
 
-fn verify_and_parse(
-    pid: libc::pid_t,
+/// The address a possibly tagged pointer refers to.
+pub fn strip_pointer_tag(value: u64, tag_mask: u64) -> u64 {
+    value & !tag_mask
+}
+
+fn read_header(reader: &dyn MemoryReader, addr: usize) -> Option<[u8; HEADER_SIZE]> {
+    let mut header = [0u8; HEADER_SIZE];
+    reader.read_memory(addr, &mut header).ok()?;
+    Some(header)
+}
+
+fn verify_header(
+    reader: &dyn MemoryReader,
     addr: usize,
+    header: &[u8; HEADER_SIZE],
     all_ranges: &[MapRange],
-    allow_missing_magic: bool
+    allow_missing_magic: bool,
+    max_dex_size: usize
 ) -> Option<DexFileResult> {
-    let mut header = [0u8; 0x70];
-    if safe_read_memory(pid, addr, &mut header).is_err() {
-        return None;
-    }
 
     // Magic
     let mut version = String::new();
@@ -233,7 +722,7 @@ fn verify_and_parse(
     if !has_magic {
         if endian_tag != ENDIAN_CONSTANT && endian_tag != REVERSE_ENDIAN_CONSTANT { return None; }
         if header_size < 0x40 || header_size > 0x200 { return None; } // relax 0x70
-        if file_size < MIN_DEX_SIZE || file_size > MAX_DEX_SIZE { return None; }
+        if file_size < MIN_DEX_SIZE || file_size > max_dex_size { return None; }
         if map_off < header_size || map_off >= file_size { return None; }
         
         let string_ids_off = u32::from_le_bytes(header[OFF_STRING_IDS_OFF..OFF_STRING_IDS_OFF+4].try_into().unwrap()) as usize;
@@ -241,7 +730,7 @@ fn verify_and_parse(
         
         version = "unknown(wiped)".to_string();
     } else {
-        if file_size < MIN_DEX_SIZE || file_size > MAX_DEX_SIZE { return None; }
+        if file_size < MIN_DEX_SIZE || file_size > max_dex_size { return None; }
     }
 
     // Verify MapList
@@ -260,7 +749,7 @@ fn verify_and_parse(
     };
      */
     let mut map_size_buf = [0u8; 4]; // uint32_t size_;
-    if safe_read_memory(pid, map_abs_addr, &mut map_size_buf).is_err() {
+    if reader.read_memory(map_abs_addr, &mut map_size_buf).is_err() {
         return None;
     }
     let map_list_size = u32::from_le_bytes(map_size_buf) as usize;
@@ -274,10 +763,51 @@ fn verify_and_parse(
         size: file_size,
         version,
         source: "Unknown".to_string(),
+        classification: classify_map(find_map_binary(all_ranges, addr)),
+        map: find_map_binary(all_ranges, addr).map(MapInfo::from),
+        referenced_from: None,
+        reconstruction: None,
+    })
+}
+
+/// A standard dex magic or the endian tag survived, worth the cost of a reconstruction.
+fn has_anchor(header: &[u8; HEADER_SIZE]) -> bool {
+    let endian_tag = u32::from_le_bytes(header[OFF_ENDIAN_TAG..OFF_ENDIAN_TAG+4].try_into().unwrap());
+    &header[0..4] == b"dex\n" || endian_tag == ENDIAN_CONSTANT
+}
+
+/// Rebuilds the wiped header of the candidate at `addr` from its sections.
+fn reconstruct_candidate(
+    reader: &dyn MemoryReader,
+    addr: usize,
+    header: &[u8; HEADER_SIZE],
+    all_ranges: &[MapRange],
+    max_dex_size: usize
+) -> Option<DexFileResult> {
+    let reconstruction = dex_reconstruct::reconstruct(reader, addr, header, all_ranges, max_dex_size)?;
+    info!(
+        "Rebuilt the header of the dex at 0x{:x} from its {:?}, confidence {:.2}, {} field(s) inferred",
+        addr, reconstruction.method, reconstruction.confidence, reconstruction.inferred_fields.len()
+    );
+
+    let version = if &header[0..4] == b"dex\n" {
+        String::from_utf8_lossy(&header[4..7]).to_string()
+    } else {
+        "unknown(wiped)".to_string()
+    };
+    Some(DexFileResult {
+        addr,
+        size: reconstruction.file_size,
+        version,
+        source: "Unknown".to_string(),
+        classification: classify_map(find_map_binary(all_ranges, addr)),
+        map: find_map_binary(all_ranges, addr).map(MapInfo::from),
+        referenced_from: None,
+        reconstruction: Some(reconstruction),
     })
 }
 
-fn find_map_binary(ranges: &[MapRange], addr: usize) -> Option<&MapRange> {
+pub fn find_map_binary(ranges: &[MapRange], addr: usize) -> Option<&MapRange> {
     ranges.binary_search_by(|range| {
         if addr < range.start() {
             Ordering::Greater
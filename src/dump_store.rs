@@ -0,0 +1,214 @@
+// dump_store.rs
+
+use super::manifest::MANIFEST_FILE_NAME;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Shared, content-addressed copy of every dex ever dumped under a root.
+const OBJECTS_DIR: &str = "objects";
+/// Held while a process creates a session, stores a dex or applies retention
+const LOCK_FILE: &str = ".lock";
+
+pub const DEFAULT_KEEP_SESSIONS: usize = 5;
+
+/// Dump root laid out as
+/// ```text
+/// <root>/objects/<sha1>.dex              one copy per distinct dex
+/// <root>/<YYYYmmdd-HHMMSS>_<pid>/        one directory per session
+///     <sha1>.dex                         hard link into objects/
+///     manifest.json
+/// ```
+/// Nothing is ever removed except by `apply_retention`. Several processes may share a root
+/// (every process of a package, or an `output_dir` without `{package}`), so changes to it
+/// happen under an exclusive `flock` of `<root>/.lock`.
+pub struct DumpStore {
+    root: PathBuf,
+}
+
+/// Where one session writes its dex files and manifest.
+pub struct SessionDir {
+    pub name: String,
+    pub path: String,
+    root: PathBuf,
+    objects: PathBuf,
+}
+
+/// Where a session's dex files and manifest end up: a local `SessionDir`,
+/// or the companion process writing one on our behalf.
+pub trait DumpSink: Send {
+    /// Directory the session writes to, for logs and the manifest
+    fn location(&self) -> &str;
+    /// Stores `data` as `<sha1>.dex`
+    fn store_dex(&self, data: &[u8], sha1: &str) -> io::Result<StoredDex>;
+    /// Replaces `manifest.json` with `json` and returns its path
+    fn write_manifest(&self, json: &[u8]) -> io::Result<String>;
+}
+
+/// Result of storing one dex in a session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredDex {
+    pub path: String,
+    /// The same content was already dumped by an earlier session
+    pub seen_in_store: bool,
+    /// The same content was already dumped earlier in this session
+    pub seen_in_session: bool,
+}
+
+impl DumpStore {
+    pub fn new(root: &str) -> Self {
+        Self { root: PathBuf::from(root) }
+    }
+
+    /// Creates `<root>/<YYYYmmdd-HHMMSS>_<pid>` for a new session.
+    pub fn create_session(&self, pid: libc::pid_t) -> io::Result<SessionDir> {
+        let _lock = StoreLock::acquire(&self.root)?;
+        let objects = self.root.join(OBJECTS_DIR);
+        fs::create_dir_all(&objects)?;
+
+        let name = format!("{}_{}", local_timestamp(), pid);
+        let path = self.root.join(&name);
+        fs::create_dir_all(&path)?;
+
+        Ok(SessionDir {
+            name,
+            path: path.to_string_lossy().into_owned(),
+            root: self.root.clone(),
+            objects,
+        })
+    }
+
+    /// Session directories, oldest first.
+    pub fn list_sessions(&self) -> io::Result<Vec<PathBuf>> {
+        let mut sessions: Vec<PathBuf> = fs::read_dir(&self.root)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .filter(|e| e.file_name() != OBJECTS_DIR)
+            .map(|e| e.path())
+            .collect();
+        // Names start with the timestamp, so lexical order is chronological
+        sessions.sort();
+        Ok(sessions)
+    }
+
+    /// Keeps the newest `keep` sessions, then drops objects no session links to anymore.
+    /// Sessions of processes that are still running are never removed.
+    pub fn apply_retention(&self, keep: usize) -> io::Result<()> {
+        let _lock = StoreLock::acquire(&self.root)?;
+        let sessions = self.list_sessions()?;
+        let expired = sessions.len().saturating_sub(keep);
+        for session in &sessions[..expired] {
+            if session_owner_alive(session) {
+                info!("Keeping dump session {}, its process is still running", session.display());
+                continue;
+            }
+            match fs::remove_dir_all(session) {
+                Ok(_) => info!("Removed old dump session {}", session.display()),
+                Err(e) => warn!("Failed to remove old dump session {}: {}", session.display(), e),
+            }
+        }
+
+        let objects = self.root.join(OBJECTS_DIR);
+        let Ok(entries) = fs::read_dir(&objects) else {
+            return Ok(());
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            if entry.path().extension().is_some_and(|ext| ext == "tmp") {
+                continue;
+            }
+            // Only the objects/ entry itself is left
+            if entry.metadata().map(|m| m.nlink() <= 1).unwrap_or(false) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DumpSink for SessionDir {
+    fn location(&self) -> &str {
+        &self.path
+    }
+
+    /// Writes the content only once per store, the session gets a hard link to it.
+    fn store_dex(&self, data: &[u8], sha1: &str) -> io::Result<StoredDex> {
+        // Retention in another process must not sweep the object before it is linked
+        let _lock = StoreLock::acquire(&self.root)?;
+        let file_name = format!("{}.dex", sha1);
+        let object = self.objects.join(&file_name);
+        let target = Path::new(&self.path).join(&file_name);
+
+        let seen_in_session = target.exists();
+        let seen_in_store = object.exists();
+        if !seen_in_store {
+            let tmp = self.objects.join(format!("{}.tmp", file_name));
+            fs::write(&tmp, data)?;
+            fs::rename(&tmp, &object)?;
+        }
+
+        if !seen_in_session && fs::hard_link(&object, &target).is_err() {
+            // e.g. a filesystem without hard links, fall back to a plain copy
+            fs::copy(&object, &target)?;
+        }
+
+        Ok(StoredDex {
+            path: target.to_string_lossy().into_owned(),
+            seen_in_store,
+            seen_in_session,
+        })
+    }
+
+    /// Goes through a temp file so readers never see a half-written manifest.
+    fn write_manifest(&self, json: &[u8]) -> io::Result<String> {
+        let path = format!("{}/{}", self.path, MANIFEST_FILE_NAME);
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, json)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(path)
+    }
+}
+
+/// Exclusive `flock` on `<root>/.lock`, released on drop.
+struct StoreLock {
+    _file: File,
+}
+
+impl StoreLock {
+    fn acquire(root: &Path) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        let file = File::options().create(true).truncate(false).write(true).open(root.join(LOCK_FILE))?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { _file: file })
+    }
+}
+
+/// Whether the pid in a `<timestamp>_<pid>` session name is a running process.
+fn session_owner_alive(session: &Path) -> bool {
+    let Some(pid) = session
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.rsplit_once('_'))
+        .and_then(|(_, pid)| pid.parse::<libc::pid_t>().ok())
+    else {
+        return false;
+    };
+    // EPERM: alive, but owned by another uid
+    pid > 0 && (unsafe { libc::kill(pid, 0) } == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM))
+}
+
+/// `YYYYmmdd-HHMMSS` in local time.
+fn local_timestamp() -> String {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&now, &mut tm) };
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec
+    )
+}
@@ -1,17 +1,29 @@
 use jni::{JNIEnv, JavaVM};
 use jni::objects::JString;
 use jni::sys::{jstring, JavaVM as RawJavaVM};
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
-use std::{
-    os::fd::{AsFd, AsRawFd},
-    time::Duration,
-};
+use std::os::fd::{AsFd, AsRawFd};
+use std::os::unix::net::UnixStream;
+use dump_store::DumpSink;
+use std::sync::{Arc, Mutex};
 use log::{warn, error, info, debug};
-use zygisk_rs::{Api, AppSpecializeArgs, Module, ServerSpecializeArgs, register_zygisk_module};
-mod art_runtime;
-mod dex_parser;
-mod dex_scanner;
+use zygisk_rs::{Api, AppSpecializeArgs, Module, ServerSpecializeArgs, register_zygisk_companion, register_zygisk_module};
+pub mod art_runtime;
+pub mod companion;
+pub mod config;
+pub mod control;
+pub mod dex_dumper;
+pub mod dex_parser;
+pub mod dex_reconstruct;
+pub mod dex_repair;
+pub mod dex_scanner;
+pub mod dump_store;
+pub mod manifest;
+pub mod maps;
+pub mod memory_reader;
+pub mod scheduler;
+pub mod session;
+pub mod snapshot;
+pub mod targets;
 
 const LOG_TAG: &str = "RustDexUnpacker";
 
@@ -21,26 +33,22 @@ unsafe impl Send for SendableVmPtr {}
 struct MyModule {
     api: Api,
     vm: JavaVM,
-    should_scan: bool,
-    force_resolve: bool,
+    /// Settings of the specializing package, `None` unless it is enabled
+    config: Option<config::PackageConfig>,
+    /// Connection to the root companion, only available before specialization
+    companion: Option<UnixStream>,
 }
 
 impl Module for MyModule {
     fn new(api: Api, env: *mut jni_sys::JNIEnv) -> Self {
-        android_logger::init_once(
-            android_logger::Config::default()
-                .with_max_level(log::LevelFilter::Info)
-                .with_tag(LOG_TAG),
-        );
+        companion::init_logger(LOG_TAG);
         let env = unsafe { JNIEnv::from_raw(env.cast()).unwrap() };
         let vm = env.get_java_vm().expect("Failed to get JavaVM");
-        let should_scan: bool = false;
-        let force_resolve: bool = false;
         Self {
             api,
             vm,
-            should_scan,
-            force_resolve,
+            config: None,
+            companion: None,
         }
     }
 
@@ -56,57 +64,43 @@ impl Module for MyModule {
 
         let mut env = self.vm.get_env().expect("Failed to get JNIEnv for pre_app_specialize");
 
-        let fd_num = module_dir_fd.as_fd().as_raw_fd();
-        let whitelist_path = std::path::PathBuf::from(format!("/proc/self/fd/{}/whitelist.txt", fd_num));
-        match std::fs::File::open(&whitelist_path) {
-            Ok(file) => {
-                let reader = BufReader::new(file);
-                let whitelist: std::collections::HashSet<String> = reader
-                    .lines()
-                    .filter_map(|line| line.ok())
-                    .map(|line| line.trim().to_string())
-                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
-                    .collect();
-
-                let package_name = unsafe { JString::from_raw(*args.nice_name as jstring) };
-                
-                let package_name = env
-                    .get_string(&package_name)
-                    .map(|java_str| java_str.to_string_lossy().into_owned())
-                    .unwrap_or_else(|e| {
-                        error!("Failed to get package name: {:?}", e);
-                        "unknown".to_string()
-                    });
-
-                if whitelist.contains(&package_name.to_string()) {
-                    info!("Package {} in whitelist, Setting scan flag to true", package_name);
-                    self.should_scan = true;
-                } else {
-                    self.should_scan = false;
-                }
-            }
-            Err(e) => {
-                debug!("Whitelist parsing error {:?}: {}", whitelist_path, e);
-            }
-        }
+        let package_name = unsafe { JString::from_raw(*args.nice_name as jstring) };
+        let package_name = env
+            .get_string(&package_name)
+            .map(|java_str| java_str.to_string_lossy().into_owned())
+            .unwrap_or_else(|e| {
+                error!("Failed to get package name: {:?}", e);
+                "unknown".to_string()
+            });
 
-        let force_resolve_settings_path  = std::path::PathBuf::from(format!("/proc/self/fd/{}/force_resolve.txt", fd_num));
-        match std::fs::File::open(&force_resolve_settings_path) {
-            Ok(_file) => {
-                self.force_resolve = true;
-            }
-            Err(_e) => {
-                self.force_resolve = false;
+        let app_data_dir = (!(*args.app_data_dir).is_null())
+            .then(|| unsafe { JString::from_raw(*args.app_data_dir as jstring) })
+            .and_then(|dir| env.get_string(&dir).ok().map(|s| s.to_string_lossy().into_owned()));
+        let process = targets::ProcessInfo::new(&package_name, app_data_dir.as_deref(), *args.uid as u32);
+
+        let fd_num = module_dir_fd.as_fd().as_raw_fd();
+        let module_dir = std::path::PathBuf::from(format!("/proc/self/fd/{}", fd_num));
+        let (package_config, decided_by) = config::ModuleConfig::load(&module_dir).for_process(&process);
+        if package_config.enabled {
+            info!("Process {} enabled by {}, Setting scan flag to true", process, decided_by);
+            if package_config.companion {
+                self.connect_companion();
             }
+            self.config = Some(package_config);
+        } else {
+            debug!("Process {} not enabled, decided by {}", process, decided_by);
+            self.config = None;
         }
         self.api.set_option(zygisk_rs::ModuleOption::ForceDenylistUnmount);
     }
 
     fn post_app_specialize(&mut self, args: &AppSpecializeArgs) {
-        if !self.should_scan {
+        let companion = self.companion.take();
+        let Some(config) = self.config.take() else {
             self.api.set_option(zygisk_rs::ModuleOption::DlcloseModuleLibrary);
             return;
-        }
+        };
+        log::set_max_level(config.level_filter());
 
         let mut env = self.vm.get_env().expect("Failed to get JNIEnv for post_app_specialize");
         let package_name = unsafe { JString::from_raw(*args.nice_name as jstring) };
@@ -120,14 +114,68 @@ impl Module for MyModule {
 
         info!("Dump dex for {}, Spawning scanner thread...", package_name);
 
+        self.spawn_scanner(package_name, config, companion);
+    }
+
+    fn pre_server_specialize(&mut self, _args: &mut ServerSpecializeArgs) {
+        let Some(module_dir_fd) = self.api.get_module_dir() else {
+            warn!("Can not get module dir");
+            return;
+        };
+
+        let fd_num = module_dir_fd.as_fd().as_raw_fd();
+        let module_dir = std::path::PathBuf::from(format!("/proc/self/fd/{}", fd_num));
+        let server_config = config::ModuleConfig::load(&module_dir).for_system_server();
+        if server_config.enabled {
+            info!("system_server enabled in config, Setting scan flag to true");
+            if server_config.companion {
+                self.connect_companion();
+            }
+            self.config = Some(server_config);
+        } else {
+            debug!("system_server not enabled in config");
+            self.config = None;
+        }
+    }
+
+    fn post_server_specialize(&mut self, _args: &ServerSpecializeArgs) {
+        let companion = self.companion.take();
+        let Some(config) = self.config.take() else {
+            self.api.set_option(zygisk_rs::ModuleOption::DlcloseModuleLibrary);
+            return;
+        };
+        log::set_max_level(config.level_filter());
+
+        info!("Dump dex for {}, Spawning scanner thread...", config::SYSTEM_SERVER);
+        self.spawn_scanner(config::SYSTEM_SERVER.to_string(), config, companion);
+    }
+}
+
+impl MyModule {
+    /// Connects to the root companion, only possible before specialization.
+    fn connect_companion(&mut self) {
+        self.companion = self.api.connect_companion();
+        match &self.companion {
+            Some(stream) => {
+                // Zygote closes every fd it does not know about while specializing
+                if !self.api.exempt_fd(stream.as_raw_fd()) {
+                    warn!("Can not keep the companion socket open");
+                    self.companion = None;
+                }
+            }
+            None => warn!("Can not connect to companion, dumps stay in the process"),
+        }
+    }
+
+    /// Runs the scan rounds for `package_name` (the process name, for system_server) on a new
+    /// thread, writing through `companion` when it accepts the session.
+    fn spawn_scanner(&self, package_name: String, config: config::PackageConfig, companion: Option<UnixStream>) {
         let vm_ptr: *mut RawJavaVM = self.vm.get_java_vm_pointer();
         
         // Cast the pointer to usize before wrapping
         let sendable_vm_ptr = SendableVmPtr(vm_ptr as usize);
-        let should_force_resolve = self.force_resolve;
         std::thread::spawn(move || {
-            info!("--- Starting DEX Scan for {} (Waiting 10s & Deep Search: true) ---", package_name);
-            std::thread::sleep(Duration::from_secs(10));
+            info!("--- Starting DEX Scan for {} (Waiting {}s & Deep Search: {}) ---", package_name, config.delay_secs, config.deep_search);
 
             let vm_ptr = sendable_vm_ptr.0 as *mut RawJavaVM;
             let vm = match unsafe { JavaVM::from_raw(vm_ptr) } {
@@ -138,78 +186,63 @@ impl Module for MyModule {
                 }
             };
             
-            let dump_dir = format!("/data/data/{}/files/rust_dumps", package_name);
-            match std::fs::remove_dir_all(&dump_dir) {
-                Ok(_) => {}
-                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
-                Err(_e) => {}
-            }
-
-            match dex_scanner::scan_memory(true) {
-                Ok(results) => {
-                    if results.is_empty() {
-                        info!("No DEX files found in suspicious memory regions");
-                    } else {
-                        info!("Found {} potential DEX files:", results.len());
-
-                        match std::fs::create_dir_all(&dump_dir) {
-                            Ok(_) => {
-                                for (i, dex) in results.iter().enumerate() {
-                                    info!("Found dex [{}]: Address=0x{:x}, Size=0x{:.x} ({}), Version: {}, Source: {}", i, dex.addr, dex.size, dex.size, dex.version, dex.source);
-                                    let pid = std::process::id() as libc::pid_t;
-                                    match dex_parser::parse_dex_at(pid, dex.addr) {
-                                        Ok(dex_file) => {
-                                            if !dex_file.methods.is_empty() {
-                                                info!( "Parsed DEX #{} ({} methods), triggering method resolution...", i, dex_file.methods.len());
-                                                if should_force_resolve {
-                                                    art_runtime::force_resolve_methods(&vm, &dex_file.methods);
-                                                }
-                                            } else {
-                                                info!("Parsed DEX #{} has no methods, skipping resolution.", i);
-                                            }
-                                            
-                                            if let Err(e) =
-                                                dump_dex_to_file(&dump_dir, i, dex.addr, dex.size)
-                                            {
-                                                error!("Failed to save DEX #{}, error: {}", i, e);
-                                            }
-                                        }
-                                        Err(e) => {
-                                            error!("Failed to parse DEX #{}, error: {}", i, e);
-                                        }
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to create DEX save dir {} error: {}", dump_dir, e);
-                            }
-                        }
+            let pid = std::process::id() as libc::pid_t;
+            let store = dump_store::DumpStore::new(&config.output_dir);
+            let companion_sink = companion.and_then(|stream| {
+                match companion::CompanionSink::open(stream, &package_name, pid, config.keep_sessions) {
+                    Ok(sink) => Some(sink),
+                    Err(e) => {
+                        warn!("Companion unavailable, falling back to {}: {}", config.output_dir, e);
+                        None
                     }
                 }
-                Err(e) => {
-                    error!("Failed to scan DEX, error: {}", e);
+            });
+            let (session_name, sink, local): (String, Box<dyn DumpSink>, bool) = match companion_sink {
+                Some(sink) => {
+                    info!("Dumps of {} go through the companion to {}", package_name, sink.location());
+                    sink.forward_logs();
+                    (sink.name.clone(), Box::new(sink), false)
                 }
+                // Its local fallback is under /data/adb, which system_server cannot write
+                None if package_name == config::SYSTEM_SERVER => {
+                    error!("system_server requires the companion, nothing is dumped without it");
+                    return;
+                }
+                None => match store.create_session(pid) {
+                    Ok(dump_session) => (dump_session.name.clone(), Box::new(dump_session), true),
+                    Err(e) => {
+                        error!("Failed to create DEX save dir in {} error: {}", config.output_dir, e);
+                        return;
+                    }
+                },
+            };
+
+            let reader = Box::new(memory_reader::ProcessVmReader::new(pid));
+            let schedule = scheduler::Schedule::from(&config);
+            let (keep_sessions, control_socket) = (config.keep_sessions, config.control_socket);
+            let session_manifest = manifest::SessionManifest::new(&session_name, &package_name, pid, config);
+            let scan_session = Arc::new(Mutex::new(session::ScanSession::new(reader, pid, sink, Some(vm), session_manifest)));
+
+            if control_socket {
+                let name = control::socket_name(&package_name, pid);
+                let scan_session = scan_session.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = control::serve(&name, scan_session) {
+                        error!("Failed to open control socket @{}, error: {}", name, e);
+                    }
+                });
+            }
+
+            session::run_rounds(&scan_session, &schedule);
+            // The companion applies retention itself once we disconnect
+            if local && let Err(e) = store.apply_retention(keep_sessions) {
+                warn!("Failed to clean up old dump sessions, error: {}", e);
             }
             
             info!("--- DEX Scan Finished for {}  ---", package_name);
         });
     }
-
-    fn pre_server_specialize(&mut self, _args: &mut ServerSpecializeArgs) {}
-
-    fn post_server_specialize(&mut self, _args: &ServerSpecializeArgs) {}
 }
 
 register_zygisk_module!(MyModule);
-
-fn dump_dex_to_file(dump_dir: &str, index: usize, addr: usize, size: usize) -> std::io::Result<()> {
-    let file_path = format!("{}/dex_{}_{:x}.dex", dump_dir, index, addr);
-
-    let data_slice = unsafe { std::slice::from_raw_parts(addr as *const u8, size) };
-
-    let mut file = File::create(&file_path)?;
-    file.write_all(data_slice)?;
-
-    info!("Dex saved to {}", file_path);
-    Ok(())
-}
\ No newline at end of file
+register_zygisk_companion!(companion::handle_connection);
@@ -0,0 +1,123 @@
+// manifest.rs
+
+use super::art_runtime::ResolveStats;
+use super::config::PackageConfig;
+use super::dex_dumper::MemoryHole;
+use super::dex_parser::ValidationReport;
+use super::dex_reconstruct::Reconstruction;
+use super::dex_repair::HeaderFix;
+use super::dex_scanner::{DexClassification, PointerRef, ScanStats};
+use super::dump_store::DumpSink;
+use super::maps::MapInfo;
+use serde::Serialize;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Everything we know about one dex of the session.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DexEntry {
+    pub index: usize,
+    /// Scan round that first found this dex, starting at 1
+    pub round: u32,
+    pub address: String,
+    pub size: usize,
+    pub version: String,
+    /// "MagicScan" / "PointerScan"
+    pub source: String,
+    pub classification: Option<DexClassification>,
+    /// The mapping the dex was found in
+    pub map: Option<MapInfo>,
+    /// For pointer scan hits, the word that pointed at the dex
+    pub referenced_from: Option<PointerRef>,
+    /// The header in memory was wiped, the dump carries one rebuilt from the sections
+    pub reconstruction: Option<Reconstruction>,
+
+    // --- Parsing ---
+    pub class_count: Option<usize>,
+    /// Every method_id, references to other dex included
+    pub method_count: Option<usize>,
+    /// Methods a class of this dex defines
+    pub defined_method_count: Option<usize>,
+    /// Where the header, the map_list and the file size disagree
+    pub validation: Option<ValidationReport>,
+    /// The dex is still dumped, only resolution is skipped
+    pub parse_error: Option<String>,
+
+    pub resolution: Option<ResolveStats>,
+
+    // --- Dump ---
+    pub file: Option<String>,
+    pub dumped_size: Option<usize>,
+    pub sha1: Option<String>,
+    /// Identical content was already dumped by an earlier session or round
+    pub duplicate: bool,
+    pub header_repairs: Vec<HeaderFix>,
+    pub holes: Vec<MemoryHole>,
+    pub dump_error: Option<String>,
+}
+
+/// One scan round of a session.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundInfo {
+    pub round: u32,
+    /// Unix timestamp, seconds
+    pub started_at: u64,
+    /// Candidates returned by the scanner
+    pub candidates: usize,
+    /// Not seen in an earlier round, these got an entry in `dex_files`
+    pub new_dex: usize,
+    /// Same address and content as in an earlier round, skipped
+    pub already_seen: usize,
+    /// Verification work of the scan, when it ran live
+    pub scan_stats: Option<ScanStats>,
+}
+
+/// `manifest.json`, written next to the dumps of a session.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionManifest {
+    /// Session directory name, `<YYYYmmdd-HHMMSS>_<pid>`
+    pub session: String,
+    pub package: String,
+    pub pid: libc::pid_t,
+    /// Unix timestamps, seconds
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    /// Settings the session ran with, so a dump can be reproduced
+    pub config: PackageConfig,
+    pub rounds: Vec<RoundInfo>,
+    pub dex_files: Vec<DexEntry>,
+}
+
+impl SessionManifest {
+    pub fn new(session: &str, package: &str, pid: libc::pid_t, config: PackageConfig) -> Self {
+        Self {
+            session: session.to_string(),
+            package: package.to_string(),
+            pid,
+            started_at: unix_timestamp(),
+            finished_at: None,
+            config,
+            rounds: Vec::new(),
+            dex_files: Vec::new(),
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.finished_at = Some(unix_timestamp());
+    }
+
+    /// Writes `manifest.json` through `sink` and returns its path.
+    pub fn write_to(&self, sink: &dyn DumpSink) -> io::Result<String> {
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        sink.write_manifest(&json)
+    }
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,130 @@
+// maps.rs
+
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One line of `/proc/<pid>/maps`.
+/// Same accessors as `proc_maps::MapRange`, but constructible, so ranges can
+/// come from a saved maps file or be built by hand for offline scanning.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapRange {
+    range_start: usize,
+    range_end: usize,
+    pub offset: usize,
+    pub dev: String,
+    pub flags: String,
+    pub inode: usize,
+    pathname: Option<PathBuf>,
+}
+
+impl MapRange {
+    pub fn new(start: usize, size: usize, flags: &str, pathname: Option<PathBuf>) -> Self {
+        Self {
+            range_start: start,
+            range_end: start + size,
+            offset: 0,
+            dev: "00:00".to_string(),
+            flags: flags.to_string(),
+            inode: 0,
+            pathname,
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.range_start
+    }
+
+    pub fn size(&self) -> usize {
+        self.range_end - self.range_start
+    }
+
+    pub fn filename(&self) -> Option<&Path> {
+        self.pathname.as_deref()
+    }
+
+    pub fn is_read(&self) -> bool {
+        self.flags.as_bytes().first() == Some(&b'r')
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.flags.as_bytes().get(1) == Some(&b'w')
+    }
+
+    pub fn is_exec(&self) -> bool {
+        self.flags.as_bytes().get(2) == Some(&b'x')
+    }
+}
+
+/// Serializable description of a `MapRange`, for results and manifests.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MapInfo {
+    pub start: String,
+    pub end: String,
+    pub perms: String,
+    pub offset: usize,
+    pub dev: String,
+    pub inode: usize,
+    pub path: Option<String>,
+    /// `name` of an `[anon:name]` mapping
+    pub anon_name: Option<String>,
+}
+
+impl From<&MapRange> for MapInfo {
+    fn from(range: &MapRange) -> Self {
+        let path = range.filename().map(|p| p.to_string_lossy().into_owned());
+        let anon_name = path
+            .as_deref()
+            .and_then(|p| p.strip_prefix("[anon:"))
+            .map(|name| name.strip_suffix(']').unwrap_or(name).to_string());
+        Self {
+            start: format!("0x{:x}", range.start()),
+            end: format!("0x{:x}", range.start() + range.size()),
+            perms: range.flags.clone(),
+            offset: range.offset,
+            dev: range.dev.clone(),
+            inode: range.inode,
+            path,
+            anon_name,
+        }
+    }
+}
+
+/// Reads and parses `/proc/<pid>/maps`.
+pub fn get_process_maps(pid: libc::pid_t) -> io::Result<Vec<MapRange>> {
+    let contents = std::fs::read_to_string(format!("/proc/{}/maps", pid))?;
+    Ok(parse_maps(&contents))
+}
+
+/// Parses the text format of `/proc/<pid>/maps`, skipping malformed lines.
+pub fn parse_maps(contents: &str) -> Vec<MapRange> {
+    contents.lines().filter_map(parse_maps_line).collect()
+}
+
+// 7f9c2a000000-7f9c2a021000 rw-p 00000000 00:00 0          [anon:dalvik-main space]
+fn parse_maps_line(line: &str) -> Option<MapRange> {
+    let mut rest = line.trim();
+    let mut fields = [""; 5];
+    for field in fields.iter_mut() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        *field = &rest[..end];
+        rest = rest[end..].trim_start();
+    }
+
+    let (start, end) = fields[0].split_once('-')?;
+    let range_start = usize::from_str_radix(start, 16).ok()?;
+    let range_end = usize::from_str_radix(end, 16).ok()?;
+    if range_end < range_start || fields[1].len() < 4 {
+        return None;
+    }
+
+    Some(MapRange {
+        range_start,
+        range_end,
+        offset: usize::from_str_radix(fields[2], 16).ok()?,
+        dev: fields[3].to_string(),
+        flags: fields[1].to_string(),
+        inode: fields[4].parse().ok()?,
+        pathname: if rest.is_empty() { None } else { Some(PathBuf::from(rest)) },
+    })
+}
@@ -0,0 +1,158 @@
+// memory_reader.rs
+
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+/// Source of target memory for the scanner, parser and dumper.
+pub trait MemoryReader: Sync {
+    /// Reads up to `buf.len()` bytes at virtual address `addr`.
+    /// May return fewer bytes than requested, e.g. at the end of a mapping.
+    fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<usize, io::Error>;
+}
+
+// --- process_vm_readv ---
+
+/// Reads another (or our own) process with `process_vm_readv`.
+/// Faulting pages come back as `EFAULT` instead of a SIGSEGV.
+pub struct ProcessVmReader {
+    pid: libc::pid_t,
+}
+
+impl ProcessVmReader {
+    pub fn new(pid: libc::pid_t) -> Self {
+        Self { pid }
+    }
+
+    pub fn current() -> Self {
+        Self::new(std::process::id() as libc::pid_t)
+    }
+}
+
+impl MemoryReader for ProcessVmReader {
+    fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let local_iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let remote_iov = libc::iovec {
+            iov_base: addr as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+
+        let nread = unsafe {
+            libc::process_vm_readv(
+                self.pid,
+                &local_iov,
+                1,
+                &remote_iov,
+                1,
+                0
+            )
+        };
+
+        if nread == -1 {
+            // no panic when EFAULT (Bad address)
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(nread as usize)
+    }
+}
+
+// --- /proc/<pid>/mem ---
+
+/// Reads through `/proc/<pid>/mem`.
+/// The kernel uses FOLL_FORCE here, so pages that `process_vm_readv`
+/// rejects (e.g. PROT_NONE guard pages) are often still readable.
+pub struct ProcMemReader {
+    file: File,
+}
+
+impl ProcMemReader {
+    pub fn open(pid: libc::pid_t) -> Result<Self, io::Error> {
+        let file = File::open(format!("/proc/{}/mem", pid))?;
+        Ok(Self { file })
+    }
+}
+
+impl MemoryReader for ProcMemReader {
+    fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<usize, io::Error> {
+        self.file.read_at(buf, addr as u64)
+    }
+}
+
+// --- In-process buffers and snapshot files ---
+
+/// Treats a byte slice as the memory at `base_addr..base_addr + data.len()`.
+pub struct SliceReader<'a> {
+    base_addr: usize,
+    data: &'a [u8],
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(base_addr: usize, data: &'a [u8]) -> Self {
+        Self { base_addr, data }
+    }
+}
+
+impl MemoryReader for SliceReader<'_> {
+    fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let offset = addr
+            .checked_sub(self.base_addr)
+            .filter(|off| *off < self.data.len())
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EFAULT))?;
+
+        let n = std::cmp::min(buf.len(), self.data.len() - offset);
+        buf[..n].copy_from_slice(&self.data[offset..offset + n]);
+        Ok(n)
+    }
+}
+
+/// A region of a snapshot file that backs `addr..addr + size`.
+#[derive(Debug, Clone, Copy)]
+pub struct FileSegment {
+    pub addr: usize,
+    pub size: usize,
+    pub file_offset: u64,
+}
+
+/// Reads memory out of a snapshot file, e.g. a raw dump of one mapping
+/// or the PT_LOAD segments of a core file.
+pub struct FileReader {
+    file: File,
+    /// Sorted by `addr`, non-overlapping
+    segments: Vec<FileSegment>,
+}
+
+impl FileReader {
+    /// The whole file is the memory starting at `base_addr`.
+    pub fn open(path: &Path, base_addr: usize) -> Result<Self, io::Error> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len() as usize;
+        Ok(Self::with_segments(file, vec![FileSegment { addr: base_addr, size, file_offset: 0 }]))
+    }
+
+    pub fn with_segments(file: File, mut segments: Vec<FileSegment>) -> Self {
+        segments.sort_by_key(|s| s.addr);
+        Self { file, segments }
+    }
+
+    fn find_segment(&self, addr: usize) -> Option<&FileSegment> {
+        let idx = self.segments.partition_point(|s| s.addr + s.size <= addr);
+        self.segments.get(idx).filter(|s| s.addr <= addr)
+    }
+}
+
+impl MemoryReader for FileReader {
+    fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let segment = self
+            .find_segment(addr)
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EFAULT))?;
+
+        let offset = addr - segment.addr;
+        let n = std::cmp::min(buf.len(), segment.size - offset);
+        self.file.read_at(&mut buf[..n], segment.file_offset + offset as u64)
+    }
+}
@@ -0,0 +1,63 @@
+// scheduler.rs
+
+use super::config::PackageConfig;
+use std::time::Duration;
+
+/// When to run the scan rounds of a session. Packers often decrypt secondary
+/// dex on the first activity or later, so one launch is scanned more than once.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    /// Before the first round
+    pub first_delay: Duration,
+    /// Before the second round
+    pub interval: Duration,
+    /// Every later interval is the previous one times this
+    pub backoff: f64,
+    pub max_rounds: u32,
+    /// No round starts later than this after the session began, zero means no limit
+    pub max_duration: Duration,
+}
+
+impl Schedule {
+    /// A single round after `delay`.
+    pub fn once(delay: Duration) -> Self {
+        Self {
+            first_delay: delay,
+            interval: Duration::ZERO,
+            backoff: 1.0,
+            max_rounds: 1,
+            max_duration: Duration::ZERO,
+        }
+    }
+
+    /// How long to wait before round `round` (0-based), given the time already spent in the
+    /// session. `None` once the schedule is over.
+    pub fn delay_before(&self, round: u32, elapsed: Duration) -> Option<Duration> {
+        if round >= self.max_rounds {
+            return None;
+        }
+
+        // A delay too large for a Duration ends the schedule instead of panicking
+        let delay = match round {
+            0 => self.first_delay,
+            n => Duration::try_from_secs_f64(self.interval.as_secs_f64() * self.backoff.powi(n as i32 - 1)).ok()?,
+        };
+        let start = elapsed.checked_add(delay)?;
+        if !self.max_duration.is_zero() && start > self.max_duration {
+            return None;
+        }
+        Some(delay)
+    }
+}
+
+impl From<&PackageConfig> for Schedule {
+    fn from(config: &PackageConfig) -> Self {
+        Self {
+            first_delay: Duration::from_secs(config.delay_secs),
+            interval: Duration::from_secs(config.round_interval_secs),
+            backoff: config.backoff,
+            max_rounds: config.rounds,
+            max_duration: Duration::from_secs(config.max_duration_secs),
+        }
+    }
+}
@@ -0,0 +1,249 @@
+// session.rs
+
+use super::art_runtime;
+use super::dex_dumper;
+use super::dex_parser::{self, ValidationReport};
+use super::dex_scanner::{self, DexFileResult, ScanOptions, ScanStats};
+use super::dump_store::DumpSink;
+use super::manifest::{unix_timestamp, DexEntry, RoundInfo, SessionManifest};
+use super::maps::MapRange;
+use super::memory_reader::MemoryReader;
+use super::scheduler::Schedule;
+use jni::JavaVM;
+use log::{error, info, warn};
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::io;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Dex handled in an earlier round, by address and SHA-1 of the bytes in memory.
+/// A dex that is decrypted in place keeps its address but changes hash, so it is dumped again.
+#[derive(Debug, Default)]
+pub struct SeenDex(HashSet<(usize, [u8; 20])>);
+
+impl SeenDex {
+    /// Returns false if `addr` with this content was already seen.
+    pub fn insert(&mut self, addr: usize, data: &[u8]) -> bool {
+        self.0.insert((addr, Sha1::digest(data).into()))
+    }
+
+    /// Adds every dex of `other`.
+    pub fn merge(&mut self, other: SeenDex) {
+        self.0.extend(other.0);
+    }
+}
+
+/// Parses, optionally resolves, and dumps every scan result not in `seen` into `session`,
+/// recording them and the round itself in `manifest`. A dex that fails to parse is dumped too.
+/// Method resolution only runs when `vm` is available and the session has `force_resolve` set.
+pub fn process_results(
+    reader: &dyn MemoryReader,
+    ranges: &[MapRange],
+    results: &[DexFileResult],
+    session: &dyn DumpSink,
+    vm: Option<&JavaVM>,
+    manifest: &mut SessionManifest,
+    seen: &mut SeenDex,
+) {
+    let mut round = RoundInfo {
+        round: manifest.rounds.len() as u32 + 1,
+        started_at: unix_timestamp(),
+        candidates: results.len(),
+        new_dex: 0,
+        already_seen: 0,
+        scan_stats: None,
+    };
+
+    for dex in results {
+        let (mut image, _) = dex_dumper::read_dex_image(reader, dex.addr, dex.size, ranges);
+        if !seen.insert(dex.addr, &image) {
+            round.already_seen += 1;
+            continue;
+        }
+        round.new_dex += 1;
+
+        let i = manifest.dex_files.len();
+        info!("Found dex [{}]: Address=0x{:x}, Size=0x{:.x} ({}), Version: {}, Source: {}, Class: {:?}", i, dex.addr, dex.size, dex.size, dex.version, dex.source, dex.classification);
+
+        let mut entry = DexEntry {
+            index: i,
+            round: round.round,
+            address: format!("0x{:x}", dex.addr),
+            size: dex.size,
+            version: dex.version.clone(),
+            source: dex.source.clone(),
+            classification: Some(dex.classification),
+            map: dex.map.clone(),
+            referenced_from: dex.referenced_from.clone(),
+            reconstruction: dex.reconstruction.clone(),
+            ..Default::default()
+        };
+
+        // Parse with the rebuilt header, the one in memory has no usable offsets
+        if let Some(reconstruction) = &dex.reconstruction {
+            info!("DEX #{} header rebuilt from its {:?}, confidence {:.2}", i, reconstruction.method, reconstruction.confidence);
+            reconstruction.apply(&mut image);
+        }
+
+        match dex_parser::parse_dex_bytes(dex.addr, &image) {
+            Ok(dex_file) => {
+                entry.class_count = Some(dex_file.class_defs.len());
+                entry.method_count = Some(dex_file.methods.len());
+                entry.defined_method_count = Some(dex_file.methods.iter().filter(|m| m.is_defined()).count());
+                entry.validation = Some(log_validation(i, dex_file.validation.clone()));
+
+                if !dex_file.methods.is_empty() {
+                    info!( "Parsed DEX #{} ({} methods), triggering method resolution...", i, dex_file.methods.len());
+                    if manifest.config.force_resolve
+                        && let Some(vm) = vm
+                    {
+                        entry.resolution = Some(art_runtime::force_resolve_methods(vm, &dex_file.methods));
+                    }
+                } else {
+                    info!("Parsed DEX #{} has no methods, skipping resolution.", i);
+                }
+            }
+            Err(failure) => {
+                // Forged headers are the dex most worth keeping, only resolution needs the parse
+                error!("Failed to parse DEX #{}, dumping it without resolution, error: {}", i, failure.error);
+                entry.parse_error = Some(failure.error.to_string());
+                entry.validation = failure.validation.map(|report| log_validation(i, report));
+            }
+        }
+
+        // Read again, resolution may have made the packer restore code in place
+        match dex_dumper::dump_dex_to_file(reader, session, i, dex.addr, dex.size, ranges, dex.reconstruction.as_ref()) {
+            Ok(report) => {
+                entry.file = Some(report.path);
+                entry.dumped_size = Some(report.dumped_size);
+                entry.sha1 = Some(report.sha1);
+                entry.duplicate = report.seen_in_store || report.seen_in_session;
+                entry.header_repairs = report.repair.fixes;
+                entry.holes = report.holes;
+            }
+            Err(e) => {
+                error!("Failed to save DEX #{}, error: {}", i, e);
+                entry.dump_error = Some(e.to_string());
+            }
+        }
+
+        manifest.dex_files.push(entry);
+    }
+
+    info!("Round {}: {} candidates, {} new, {} already seen", round.round, round.candidates, round.new_dex, round.already_seen);
+    manifest.rounds.push(round);
+}
+
+/// Logs every issue of dex `index`, then hands the report back for the manifest.
+fn log_validation(index: usize, report: ValidationReport) -> ValidationReport {
+    for issue in &report.issues {
+        warn!("DEX #{} {:?} in {}: {}", index, issue.kind, issue.section, issue.message);
+    }
+    report
+}
+
+/// Live state of one dump session of a running process, shared between the
+/// scheduled rounds and the control socket.
+pub struct ScanSession {
+    pub reader: Box<dyn MemoryReader + Send>,
+    pub pid: libc::pid_t,
+    pub options: ScanOptions,
+    pub sink: Box<dyn DumpSink>,
+    pub vm: Option<JavaVM>,
+    pub manifest: SessionManifest,
+    pub seen: SeenDex,
+    /// Fixed memory map to scan instead of `/proc/<pid>/maps`, for readers over a snapshot
+    pub ranges: Option<Vec<MapRange>>,
+}
+
+impl ScanSession {
+    pub fn new(
+        reader: Box<dyn MemoryReader + Send>,
+        pid: libc::pid_t,
+        sink: Box<dyn DumpSink>,
+        vm: Option<JavaVM>,
+        manifest: SessionManifest,
+    ) -> Self {
+        Self {
+            reader,
+            pid,
+            options: manifest.config.scan_options(),
+            sink,
+            vm,
+            manifest,
+            seen: SeenDex::default(),
+            ranges: None,
+        }
+    }
+
+    fn maps(&self) -> io::Result<Vec<MapRange>> {
+        match &self.ranges {
+            Some(ranges) => Ok(ranges.clone()),
+            None => dex_scanner::get_sorted_maps(self.pid),
+        }
+    }
+
+    /// Scans without dumping anything.
+    pub fn scan(&self) -> io::Result<(Vec<DexFileResult>, ScanStats)> {
+        let ranges = self.maps()?;
+        Ok(dex_scanner::scan_memory_with_stats(self.reader.as_ref(), &ranges, &self.options))
+    }
+
+    /// Scans and dumps every dex not seen in an earlier round.
+    pub fn dump_round(&mut self) -> io::Result<RoundInfo> {
+        let ranges = self.maps()?;
+        let (results, stats) = dex_scanner::scan_memory_with_stats(self.reader.as_ref(), &ranges, &self.options);
+        if results.is_empty() {
+            info!("No DEX files found in suspicious memory regions");
+        } else {
+            info!("Found {} potential DEX files:", results.len());
+        }
+        process_results(self.reader.as_ref(), &ranges, &results, self.sink.as_ref(), self.vm.as_ref(), &mut self.manifest, &mut self.seen);
+        let round = self.manifest.rounds.last_mut().unwrap();
+        round.scan_stats = Some(stats);
+        let round = round.clone();
+        self.save_manifest();
+        Ok(round)
+    }
+
+    /// Like `dump_round`, but resolves the methods of every dex found, seen or not, then dumps
+    /// them again: method-extraction packers only restore code once it is resolved.
+    pub fn resolve_round(&mut self) -> io::Result<RoundInfo> {
+        let force_resolve = self.manifest.config.force_resolve;
+        let seen = std::mem::take(&mut self.seen);
+        self.manifest.config.force_resolve = true;
+        let round = self.dump_round();
+        self.manifest.config.force_resolve = force_resolve;
+        // Dex first found by this round are seen all the same, later rounds skip them
+        let found = std::mem::replace(&mut self.seen, seen);
+        self.seen.merge(found);
+        self.save_manifest();
+        round
+    }
+
+    /// Rewrites the manifest, so a killed app still leaves one behind.
+    pub fn save_manifest(&mut self) {
+        self.manifest.finish();
+        if let Err(e) = self.manifest.write_to(self.sink.as_ref()) {
+            error!("Failed to write manifest, error: {}", e);
+        }
+    }
+}
+
+/// Runs one `dump_round` per round of `schedule`, releasing the session between rounds.
+pub fn run_rounds(session: &Mutex<ScanSession>, schedule: &Schedule) {
+    let started = Instant::now();
+
+    let mut round = 0;
+    while let Some(delay) = schedule.delay_before(round, started.elapsed()) {
+        std::thread::sleep(delay);
+        round += 1;
+        info!("Scan round {}/{}", round, schedule.max_rounds);
+
+        let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = session.dump_round() {
+            error!("Failed to scan DEX, error: {}", e);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+// snapshot.rs
+
+use super::dex_scanner::PointerWidth;
+use super::maps::{self, MapRange};
+use super::memory_reader::{FileReader, FileSegment};
+use std::fs::File;
+use std::io::{self, Error, ErrorKind};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+// --- ELF constants ---
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_CORE: u16 = 4;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_FILE: u32 = 0x46494c45; // "FILE"
+
+const MAX_NOTE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A memory snapshot that can be fed to `scan_memory` / `dump_dex_to_file`
+/// exactly like a live process.
+pub struct Snapshot {
+    pub reader: FileReader,
+    /// Sorted by start address
+    pub ranges: Vec<MapRange>,
+    /// Known for cores (their ELF class), not for raw images
+    pub pointer_width: Option<PointerWidth>,
+}
+
+/// How the regions of a raw memory image are laid out in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawLayout {
+    /// Readable regions of the maps file, back to back in maps order
+    /// (e.g. `dd` of every readable line of `maps` into one file).
+    Concatenated,
+    /// File offset == virtual address (e.g. `dd if=/proc/<pid>/mem` with `seek`).
+    Sparse,
+}
+
+/// Loads a raw memory image together with the `/proc/<pid>/maps` saved next to it.
+pub fn open_raw_image(image_path: &Path, maps_path: &Path, layout: RawLayout) -> io::Result<Snapshot> {
+    let file = File::open(image_path)?;
+    let file_len = file.metadata()?.len();
+    let contents = std::fs::read_to_string(maps_path)?;
+
+    let mut ranges: Vec<MapRange> = maps::parse_maps(&contents).into_iter().filter(|r| r.is_read()).collect();
+    let mut segments = Vec::with_capacity(ranges.len());
+
+    match layout {
+        RawLayout::Concatenated => {
+            let mut file_offset = 0u64;
+            for range in &ranges {
+                let available = file_len.saturating_sub(file_offset) as usize;
+                let size = std::cmp::min(range.size(), available);
+                if size > 0 {
+                    segments.push(FileSegment { addr: range.start(), size, file_offset });
+                }
+                file_offset += range.size() as u64;
+            }
+        }
+        RawLayout::Sparse => {
+            for range in &ranges {
+                let available = file_len.saturating_sub(range.start() as u64) as usize;
+                let size = std::cmp::min(range.size(), available);
+                if size > 0 {
+                    segments.push(FileSegment { addr: range.start(), size, file_offset: range.start() as u64 });
+                }
+            }
+        }
+    }
+
+    ranges.sort_by_key(|r| r.start());
+    Ok(Snapshot { reader: FileReader::with_segments(file, segments), ranges, pointer_width: None })
+}
+
+/// Loads an ELF core file: every PT_LOAD segment becomes a mapping,
+/// named after its backing file when the core has an NT_FILE note.
+pub fn open_core_file(path: &Path) -> io::Result<Snapshot> {
+    let file = File::open(path)?;
+    let elf = ElfReader::new(&file)?;
+
+    let mut loads = Vec::new();
+    let mut files = Vec::new();
+    for i in 0..elf.phnum {
+        let phdr = elf.program_header(i)?;
+        match phdr.p_type {
+            PT_LOAD if phdr.memsz > 0 => loads.push(phdr),
+            PT_NOTE => files.extend(elf.nt_file_entries(&phdr)?),
+            _ => {}
+        }
+    }
+
+    let mut ranges = Vec::with_capacity(loads.len());
+    let mut segments = Vec::with_capacity(loads.len());
+    for phdr in &loads {
+        let start = phdr.vaddr as usize;
+        let flags = format!(
+            "{}{}{}p",
+            if phdr.flags & PF_R != 0 { 'r' } else { '-' },
+            if phdr.flags & PF_W != 0 { 'w' } else { '-' },
+            if phdr.flags & PF_X != 0 { 'x' } else { '-' },
+        );
+
+        let backing = files.iter().find(|f| f.start <= start && start < f.end);
+        let mut range = MapRange::new(start, phdr.memsz as usize, &flags, backing.map(|f| f.path.clone()));
+        if let Some(f) = backing {
+            range.offset = f.file_offset + (start - f.start);
+        }
+        ranges.push(range);
+
+        // The kernel omits unreadable / file-backed read-only pages, memsz > filesz
+        if phdr.filesz > 0 {
+            segments.push(FileSegment { addr: start, size: phdr.filesz as usize, file_offset: phdr.offset });
+        }
+    }
+
+    let pointer_width = Some(if elf.is_64 { PointerWidth::Bits64 } else { PointerWidth::Bits32 });
+    ranges.sort_by_key(|r| r.start());
+    Ok(Snapshot { reader: FileReader::with_segments(file, segments), ranges, pointer_width })
+}
+
+struct ProgramHeader {
+    p_type: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+    memsz: u64,
+}
+
+/// One entry of an NT_FILE note: `start..end` is mapped from `path` at `file_offset`.
+struct MappedFile {
+    start: usize,
+    end: usize,
+    file_offset: usize,
+    path: PathBuf,
+}
+
+/// Just enough of ELF32/ELF64 (little-endian) to walk the program headers of a core.
+struct ElfReader<'a> {
+    file: &'a File,
+    is_64: bool,
+    phoff: u64,
+    phentsize: u64,
+    phnum: u64,
+}
+
+impl<'a> ElfReader<'a> {
+    fn new(file: &'a File) -> io::Result<Self> {
+        let mut ident = [0u8; 64];
+        file.read_exact_at(&mut ident, 0)?;
+        if &ident[0..4] != b"\x7fELF" {
+            return Err(Error::new(ErrorKind::InvalidData, "Not an ELF file"));
+        }
+        if ident[5] != ELFDATA2LSB {
+            return Err(Error::new(ErrorKind::InvalidData, "Only little-endian cores are supported"));
+        }
+        let is_64 = match ident[4] {
+            ELFCLASS64 => true,
+            ELFCLASS32 => false,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "Unknown ELF class")),
+        };
+        if u16_at(&ident, 0x10) != ET_CORE {
+            return Err(Error::new(ErrorKind::InvalidData, "Not an ELF core file"));
+        }
+
+        let (phoff, phentsize, phnum) = if is_64 {
+            (u64_at(&ident, 0x20), u16_at(&ident, 0x36), u16_at(&ident, 0x38))
+        } else {
+            (u32_at(&ident, 0x1C) as u64, u16_at(&ident, 0x2A), u16_at(&ident, 0x2C))
+        };
+
+        Ok(Self { file, is_64, phoff, phentsize: phentsize as u64, phnum: phnum as u64 })
+    }
+
+    fn program_header(&self, index: u64) -> io::Result<ProgramHeader> {
+        let mut buf = [0u8; 56];
+        let len = if self.is_64 { 56 } else { 32 };
+        self.file.read_exact_at(&mut buf[..len], self.phoff + index * self.phentsize)?;
+
+        Ok(if self.is_64 {
+            ProgramHeader {
+                p_type: u32_at(&buf, 0x00),
+                flags: u32_at(&buf, 0x04),
+                offset: u64_at(&buf, 0x08),
+                vaddr: u64_at(&buf, 0x10),
+                filesz: u64_at(&buf, 0x20),
+                memsz: u64_at(&buf, 0x28),
+            }
+        } else {
+            ProgramHeader {
+                p_type: u32_at(&buf, 0x00),
+                offset: u32_at(&buf, 0x04) as u64,
+                vaddr: u32_at(&buf, 0x08) as u64,
+                filesz: u32_at(&buf, 0x10) as u64,
+                memsz: u32_at(&buf, 0x14) as u64,
+                flags: u32_at(&buf, 0x18),
+            }
+        })
+    }
+
+    /// Walks a PT_NOTE segment and decodes its NT_FILE note, if any.
+    fn nt_file_entries(&self, phdr: &ProgramHeader) -> io::Result<Vec<MappedFile>> {
+        if phdr.filesz > MAX_NOTE_SIZE {
+            return Ok(Vec::new());
+        }
+        let mut notes = vec![0u8; phdr.filesz as usize];
+        self.file.read_exact_at(&mut notes, phdr.offset)?;
+
+        let mut pos = 0;
+        while pos + 12 <= notes.len() {
+            let namesz = u32_at(&notes, pos) as usize;
+            let descsz = u32_at(&notes, pos + 4) as usize;
+            let n_type = u32_at(&notes, pos + 8);
+            let desc_start = pos + 12 + align4(namesz);
+            let desc_end = desc_start.saturating_add(descsz);
+            if desc_end > notes.len() {
+                break;
+            }
+            if n_type == NT_FILE {
+                return Ok(self.parse_nt_file(&notes[desc_start..desc_end]));
+            }
+            pos = desc_start + align4(descsz);
+        }
+        Ok(Vec::new())
+    }
+
+    // count, page_size, [start, end, file_ofs] * count, filenames...
+    fn parse_nt_file(&self, desc: &[u8]) -> Vec<MappedFile> {
+        let word = if self.is_64 { 8 } else { 4 };
+        let read_word = |off: usize| -> Option<usize> {
+            let bytes = desc.get(off..off + word)?;
+            Some(if self.is_64 { u64_at(bytes, 0) as usize } else { u32_at(bytes, 0) as usize })
+        };
+
+        let (Some(count), Some(page_size)) = (read_word(0), read_word(word)) else {
+            return Vec::new();
+        };
+        let names_off = match count.checked_mul(3 * word).and_then(|n| n.checked_add(2 * word)) {
+            Some(off) if off <= desc.len() => off,
+            _ => return Vec::new(),
+        };
+
+        let mut names = desc[names_off..].split(|b| *b == 0);
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let base = 2 * word + i * 3 * word;
+            let (Some(start), Some(end), Some(pgoff), Some(name)) =
+                (read_word(base), read_word(base + word), read_word(base + 2 * word), names.next())
+            else {
+                break;
+            };
+            entries.push(MappedFile {
+                start,
+                end,
+                file_offset: pgoff.saturating_mul(page_size),
+                path: PathBuf::from(String::from_utf8_lossy(name).into_owned()),
+            });
+        }
+        entries
+    }
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+fn u16_at(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(buf[off..off + 2].try_into().unwrap())
+}
+
+fn u32_at(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
@@ -0,0 +1,208 @@
+// targets.rs
+
+use regex::Regex;
+use serde::Deserialize;
+use std::fmt;
+
+/// Multi-user uid layout: `uid = user_id * PER_USER_RANGE + app_id`.
+const PER_USER_RANGE: u32 = 100_000;
+
+/// The process being specialized, as seen by the target rules.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessInfo {
+    /// `nice_name`, e.g. `com.foo:push`
+    pub process: String,
+    /// Last component of `app_data_dir`, `None` for isolated processes without one
+    pub package: Option<String>,
+    pub uid: u32,
+}
+
+impl ProcessInfo {
+    pub fn new(process: &str, app_data_dir: Option<&str>, uid: u32) -> Self {
+        let package = app_data_dir
+            .and_then(|dir| dir.trim_end_matches('/').rsplit('/').next())
+            .filter(|name| !name.is_empty())
+            .map(str::to_string);
+        Self { process: process.to_string(), package, uid }
+    }
+
+    /// The uid without its user, the same for a package in every profile.
+    pub fn app_id(&self) -> u32 {
+        self.uid % PER_USER_RANGE
+    }
+}
+
+impl fmt::Display for ProcessInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (package {}, uid {})", self.process, self.package.as_deref().unwrap_or("-"), self.uid)
+    }
+}
+
+/// Inclusive uid or app ID range, `10123` or `"10100-10199"` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "IdRangeSpec")]
+pub struct IdRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IdRangeSpec {
+    One(u32),
+    Range(String),
+}
+
+impl TryFrom<IdRangeSpec> for IdRange {
+    type Error = String;
+
+    fn try_from(spec: IdRangeSpec) -> Result<Self, Self::Error> {
+        let (start, end) = match spec {
+            IdRangeSpec::One(id) => (id, id),
+            IdRangeSpec::Range(text) => {
+                let parse = |s: &str| s.trim().parse::<u32>().map_err(|_| format!("invalid id range '{}'", text));
+                match text.split_once('-') {
+                    Some((start, end)) => (parse(start)?, parse(end)?),
+                    None => {
+                        let id = parse(&text)?;
+                        (id, id)
+                    }
+                }
+            }
+        };
+        if start > end {
+            return Err(format!("id range {}-{} is empty", start, end));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl IdRange {
+    pub fn contains(&self, id: u32) -> bool {
+        (self.start..=self.end).contains(&id)
+    }
+}
+
+/// `process_regex` of a rule, compiled once when the config is loaded.
+#[derive(Debug, Clone)]
+pub struct ProcessRegex {
+    pub pattern: String,
+    /// The compile error of an invalid pattern, which then matches nothing
+    pub compiled: Result<Regex, regex::Error>,
+}
+
+impl<'de> Deserialize<'de> for ProcessRegex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        let compiled = Regex::new(&pattern);
+        Ok(Self { pattern, compiled })
+    }
+}
+
+impl ProcessRegex {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.compiled.as_ref().is_ok_and(|re| re.is_match(text))
+    }
+}
+
+/// One `[[targets]]` entry. Every condition that is set must match; a rule without
+/// conditions matches nothing.
+/// ```toml
+/// [[targets]]
+/// name = "foo and its subprocesses"
+/// process = "com.foo*"
+///
+/// [[targets]]
+/// process_regex = ':(sandboxed|isolated)\d*$'
+/// exclude = true
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TargetRule {
+    /// Shown in the log when the rule matches
+    pub name: Option<String>,
+    /// Glob on the process name, `*` and `?`
+    pub process: Option<String>,
+    /// Regex on the process name, unanchored
+    pub process_regex: Option<ProcessRegex>,
+    /// Glob on the package from `app_data_dir`
+    pub package: Option<String>,
+    pub uid: Option<IdRange>,
+    /// uid modulo the per-user range, matches every profile
+    pub app_id: Option<IdRange>,
+    /// A matching exclude rule wins over every include
+    #[serde(default)]
+    pub exclude: bool,
+}
+
+impl TargetRule {
+    pub fn has_conditions(&self) -> bool {
+        self.process.is_some() || self.process_regex.is_some() || self.package.is_some() || self.uid.is_some() || self.app_id.is_some()
+    }
+
+    /// Whether every condition holds for `process`. An invalid regex never matches.
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        if !self.has_conditions() {
+            return false;
+        }
+        if let Some(pattern) = &self.process
+            && !glob_match(pattern, &process.process)
+        {
+            return false;
+        }
+        if let Some(regex) = &self.process_regex
+            && !regex.is_match(&process.process)
+        {
+            return false;
+        }
+        if let Some(pattern) = &self.package
+            && !process.package.as_deref().is_some_and(|package| glob_match(pattern, package))
+        {
+            return false;
+        }
+        if let Some(range) = self.uid
+            && !range.contains(process.uid)
+        {
+            return false;
+        }
+        if let Some(range) = self.app_id
+            && !range.contains(process.app_id())
+        {
+            return false;
+        }
+        true
+    }
+
+    /// `name`, or `targets[<index>]`.
+    pub fn describe(&self, index: usize) -> String {
+        match &self.name {
+            Some(name) => format!("targets[{}] '{}'", index, name),
+            None => format!("targets[{}]", index),
+        }
+    }
+}
+
+/// Glob with `*` (any run, including `:` and `.`) and `?` (one character).
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and the text position it currently swallows up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
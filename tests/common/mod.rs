@@ -0,0 +1,209 @@
+// Hand-built dex images shared by the parser and reconstruction tests.
+#![allow(dead_code)]
+
+// Header fields used by the tests
+pub const FILE_SIZE: usize = 0x20;
+pub const MAP_OFF: usize = 0x34;
+pub const STRING_IDS_SIZE: usize = 0x38;
+pub const STRING_IDS_OFF: usize = 0x3c;
+pub const TYPE_IDS_OFF: usize = 0x44;
+pub const PROTO_IDS_OFF: usize = 0x4c;
+
+/// Sorted, as the format requires
+pub const STRINGS: [&str; 10] = ["<init>", "Foo.java", "I", "LFoo;", "LIface;", "Ljava/lang/Object;", "MAX", "V", "count", "run"];
+/// Type ids: I, LFoo;, LIface;, Ljava/lang/Object;, V
+const TYPE_STRINGS: [u32; 5] = [2, 3, 4, 5, 7];
+
+pub fn u32_at(data: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(data[off..off + 4].try_into().unwrap())
+}
+
+pub fn put_u32(data: &mut [u8], off: usize, value: u32) {
+    data[off..off + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn uleb128(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub fn sleb128(out: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// A `code_item` with `tries` (`start_addr`, `insn_count`, `handler_off`) followed by the raw
+/// `handlers` list, padded after the insns when their count is odd.
+pub fn code_item(registers: u16, ins: u16, insns: &[u16], tries: &[(u32, u16, u16)], handlers: &[u8]) -> Vec<u8> {
+    let mut code = Vec::new();
+    for value in [registers, ins, 0, tries.len() as u16] {
+        code.extend_from_slice(&value.to_le_bytes());
+    }
+    code.extend_from_slice(&0u32.to_le_bytes()); // debug_info_off
+    code.extend_from_slice(&(insns.len() as u32).to_le_bytes());
+    for insn in insns {
+        code.extend_from_slice(&insn.to_le_bytes());
+    }
+    if !tries.is_empty() {
+        if insns.len() % 2 == 1 {
+            code.extend_from_slice(&[0, 0]);
+        }
+        for &(start_addr, insn_count, handler_off) in tries {
+            code.extend_from_slice(&start_addr.to_le_bytes());
+            code.extend_from_slice(&insn_count.to_le_bytes());
+            code.extend_from_slice(&handler_off.to_le_bytes());
+        }
+        code.extend_from_slice(handlers);
+    }
+    code
+}
+
+/// `<init>()V` of `LFoo;`: invoke-direct {v0}, Object.<init>; return-void
+pub fn default_code() -> Vec<u8> {
+    code_item(1, 1, &[0x1070, 2, 0, 0x000e], &[], &[])
+}
+
+/// A dex with one class:
+/// ```text
+/// public abstract class Foo extends Object implements Iface {  // Foo.java
+///     public static final int MAX;
+///     private int count;
+///     public Foo() { <code> }
+///     public abstract void run();
+/// }
+/// ```
+/// Method ids: 0 `Foo.<init>`, 1 `Foo.run`, 2 `Object.<init>`; field ids: 0 `MAX`, 1 `count`.
+pub fn class_dex(code: &[u8]) -> Vec<u8> {
+    let mut d = vec![0u8; 0x70];
+    let w16 = |d: &mut Vec<u8>, v: u16| d.extend_from_slice(&v.to_le_bytes());
+    let w32 = |d: &mut Vec<u8>, v: u32| d.extend_from_slice(&v.to_le_bytes());
+    let align = |d: &mut Vec<u8>| d.resize(d.len().next_multiple_of(4), 0);
+
+    let string_ids = d.len();
+    d.resize(string_ids + STRINGS.len() * 4, 0);
+    let type_ids = d.len();
+    for idx in TYPE_STRINGS {
+        w32(&mut d, idx);
+    }
+    let proto_ids = d.len(); // ()V: shorty "V", return type V
+    for v in [7, 4, 0] {
+        w32(&mut d, v);
+    }
+    let field_ids = d.len();
+    for (class, type_, name) in [(1, 0, 6), (1, 0, 8)] {
+        w16(&mut d, class);
+        w16(&mut d, type_);
+        w32(&mut d, name);
+    }
+    let method_ids = d.len();
+    for (class, proto, name) in [(1, 0, 0), (1, 0, 9), (3, 0, 0)] {
+        w16(&mut d, class);
+        w16(&mut d, proto);
+        w32(&mut d, name);
+    }
+    let class_defs = d.len();
+    d.resize(class_defs + 32, 0);
+
+    let data = d.len();
+    let code_off = d.len();
+    d.extend_from_slice(code);
+    align(&mut d);
+    let type_list = d.len(); // implements LIface;
+    w32(&mut d, 1);
+    w16(&mut d, 2);
+    align(&mut d);
+    let string_data = d.len();
+    for (i, s) in STRINGS.iter().enumerate() {
+        let offset = d.len() as u32;
+        put_u32(&mut d, string_ids + i * 4, offset);
+        d.push(s.len() as u8);
+        d.extend_from_slice(s.as_bytes());
+        d.push(0);
+    }
+    let class_data = d.len();
+    let mut cd = Vec::new();
+    for count in [1, 1, 1, 1] {
+        uleb128(&mut cd, count);
+    }
+    for v in [0, 0x19] {
+        uleb128(&mut cd, v); // MAX: public static final
+    }
+    for v in [1, 0x2] {
+        uleb128(&mut cd, v); // count: private
+    }
+    for v in [0, 0x10001, code_off as u32] {
+        uleb128(&mut cd, v); // <init>: public constructor
+    }
+    for v in [1, 0x401, 0] {
+        uleb128(&mut cd, v); // run: public abstract
+    }
+    d.extend_from_slice(&cd);
+    align(&mut d);
+    for (i, v) in [1, 0x401, 3, type_list as u32, 1, 0, class_data as u32, 0].into_iter().enumerate() {
+        put_u32(&mut d, class_defs + i * 4, v);
+    }
+
+    let map = d.len();
+    let items: [(u16, u32, usize); 12] = [
+        (0x0000, 1, 0),
+        (0x0001, STRINGS.len() as u32, string_ids),
+        (0x0002, TYPE_STRINGS.len() as u32, type_ids),
+        (0x0003, 1, proto_ids),
+        (0x0004, 2, field_ids),
+        (0x0005, 3, method_ids),
+        (0x0006, 1, class_defs),
+        (0x2001, 1, code_off),
+        (0x1001, 1, type_list),
+        (0x2002, STRINGS.len() as u32, string_data),
+        (0x2000, 1, class_data),
+        (0x1000, 1, map),
+    ];
+    w32(&mut d, items.len() as u32);
+    for (type_, size, offset) in items {
+        w16(&mut d, type_);
+        w16(&mut d, 0);
+        w32(&mut d, size);
+        w32(&mut d, offset as u32);
+    }
+
+    let size = d.len();
+    d[..8].copy_from_slice(b"dex\n035\0");
+    let header = [
+        (FILE_SIZE, size),
+        (0x24, 0x70), // header_size
+        (MAP_OFF, map),
+        (STRING_IDS_SIZE, STRINGS.len()),
+        (STRING_IDS_OFF, string_ids),
+        (0x40, TYPE_STRINGS.len()),
+        (TYPE_IDS_OFF, type_ids),
+        (0x48, 1),
+        (PROTO_IDS_OFF, proto_ids),
+        (0x50, 2),
+        (0x54, field_ids),
+        (0x58, 3),
+        (0x5c, method_ids),
+        (0x60, 1),
+        (0x64, class_defs),
+        (0x68, size - data),
+        (0x6c, data),
+    ];
+    put_u32(&mut d, 0x28, 0x1234_5678); // endian_tag
+    for (off, value) in header {
+        put_u32(&mut d, off, value as u32);
+    }
+    d
+}
@@ -0,0 +1,97 @@
+// Control socket protocol over a session that scans a synthetic dex from a slice, no
+// Android or /proc of another process needed.
+
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use zygisk_rust_dex_unpacker::config::PackageConfig;
+use zygisk_rust_dex_unpacker::control;
+use zygisk_rust_dex_unpacker::dump_store::DumpStore;
+use zygisk_rust_dex_unpacker::manifest::SessionManifest;
+use zygisk_rust_dex_unpacker::maps::MapRange;
+use zygisk_rust_dex_unpacker::memory_reader::SliceReader;
+use zygisk_rust_dex_unpacker::session::ScanSession;
+
+const BASE: usize = 0x7100_0000_0000;
+const MAP_SIZE: usize = 0x1000;
+const DEX_SIZE: usize = 0x8c;
+
+/// An anonymous map holding a dex with only a header and a map_list.
+fn memory() -> Vec<u8> {
+    let mut memory = vec![0u8; MAP_SIZE];
+    memory[..8].copy_from_slice(b"dex\n035\0");
+    let mut put = |off: usize, value: u32| memory[off..off + 4].copy_from_slice(&value.to_le_bytes());
+    put(0x20, DEX_SIZE as u32); // file_size
+    put(0x24, 0x70); // header_size
+    put(0x28, 0x1234_5678); // endian_tag
+    put(0x34, 0x70); // map_off
+    put(0x68, 0x1c); // data_size
+    put(0x6c, 0x70); // data_off
+    put(0x70, 2); // map_list size
+    put(0x74, 0x0000); // header_item
+    put(0x78, 1);
+    put(0x7c, 0);
+    put(0x80, 0x1000); // map_list
+    put(0x84, 1);
+    put(0x88, 0x70);
+    memory
+}
+
+fn request(name: &str, command: &str) -> Value {
+    // The server thread may not be listening yet
+    for _ in 0..50 {
+        if let Ok(reply) = control::request(name, command) {
+            return serde_json::from_str(&reply).expect("reply is not JSON");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    panic!("control socket @{} never answered", name);
+}
+
+#[test]
+fn commands_drive_a_slice_backed_session() {
+    let pid = std::process::id() as libc::pid_t;
+    let root = std::env::temp_dir().join(format!("dexunpack-control-test-{}", pid));
+    let sink = DumpStore::new(root.to_str().unwrap()).create_session(pid).unwrap();
+
+    let memory: &'static [u8] = Box::leak(memory().into_boxed_slice());
+    let config = PackageConfig { magic_scan: true, ..Default::default() };
+    let manifest = SessionManifest::new(&sink.name, "com.example.test", pid, config);
+    let mut session = ScanSession::new(Box::new(SliceReader::new(BASE, memory)), pid, Box::new(sink), None, manifest);
+    session.ranges = Some(vec![MapRange::new(BASE, MAP_SIZE, "rw-p", None)]);
+
+    let name = control::socket_name("com.example.test", pid);
+    let session = Arc::new(Mutex::new(session));
+    let server_name = name.clone();
+    std::thread::spawn(move || control::serve(&server_name, session));
+
+    let status = request(&name, "status");
+    assert_eq!(status["ok"], true);
+    assert_eq!(status["dex_files"], 0);
+
+    let scan = request(&name, "scan");
+    assert_eq!(scan["ok"], true);
+    assert_eq!(scan["candidates"].as_array().unwrap().len(), 1);
+    assert_eq!(scan["candidates"][0]["address"], format!("0x{:x}", BASE));
+
+    // A dex first found by resolve counts as seen for the next round
+    let resolve = request(&name, "resolve");
+    assert_eq!(resolve["round"]["new_dex"], 1);
+    let dump = request(&name, "dump");
+    assert_eq!(dump["round"]["new_dex"], 0);
+    assert_eq!(dump["round"]["already_seen"], 1);
+
+    // resolve dumps seen dex again
+    let resolve = request(&name, "resolve");
+    assert_eq!(resolve["round"]["new_dex"], 1);
+
+    let list = request(&name, "list");
+    let dex_files = list["dex_files"].as_array().unwrap();
+    assert_eq!(dex_files.len(), 2);
+    assert!(dex_files.iter().all(|dex| dex["file"].is_string()));
+
+    let unknown = request(&name, "frobnicate");
+    assert_eq!(unknown["ok"], false);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,179 @@
+// Parser and validation on hand-built dex images.
+
+mod common;
+
+use common::*;
+use zygisk_rust_dex_unpacker::dex_parser::{self, IssueKind, ValidationReport};
+
+const BASE: usize = 0x7100_0000_0000;
+
+/// The report of a dex, whether it parsed or not.
+fn validation(dex: &[u8]) -> ValidationReport {
+    match dex_parser::parse_dex_bytes(BASE, dex) {
+        Ok(parsed) => parsed.validation,
+        Err(failure) => failure.validation.expect("header not readable"),
+    }
+}
+
+fn has_issue(report: &ValidationReport, kind: IssueKind, section: &str) -> bool {
+    report.issues.iter().any(|issue| issue.kind == kind && issue.section == section)
+}
+
+#[test]
+fn clean_dex_has_an_empty_report() {
+    let parsed = dex_parser::parse_dex_bytes(BASE, &class_dex(&default_code())).unwrap();
+    assert!(parsed.validation.is_clean(), "{:?}", parsed.validation.issues);
+    assert_eq!(parsed.map_list.map(|map| map.size), Some(12));
+}
+
+#[test]
+fn relocated_string_ids_disagree_with_the_map_list() {
+    let mut dex = class_dex(&default_code());
+    // A copy of string_ids at the end of the file, the header pointing at it
+    let string_ids = u32_at(&dex, STRING_IDS_OFF) as usize;
+    let copy = dex[string_ids..string_ids + STRINGS.len() * 4].to_vec();
+    let relocated = dex.len();
+    dex.extend_from_slice(&copy);
+    let file_size = dex.len() as u32;
+    put_u32(&mut dex, STRING_IDS_OFF, relocated as u32);
+    put_u32(&mut dex, FILE_SIZE, file_size);
+
+    let parsed = dex_parser::parse_dex_bytes(BASE, &dex).unwrap();
+    assert!(has_issue(&parsed.validation, IssueKind::OffsetMismatch, "string_ids"), "{:?}", parsed.validation.issues);
+    // Parsing still follows the header
+    assert_eq!(parsed.strings.len(), STRINGS.len());
+    assert_eq!(parsed.classes[0].descriptor, "LFoo;");
+}
+
+#[test]
+fn section_running_into_the_next_one_is_an_overlap() {
+    let mut dex = class_dex(&default_code());
+    // 8 type_ids run into proto_ids, 3 entries after the real 5
+    put_u32(&mut dex, 0x40, 8);
+
+    let report = validation(&dex);
+    assert!(has_issue(&report, IssueKind::Overlap, "type_ids"), "{:?}", report.issues);
+    assert!(has_issue(&report, IssueKind::CountMismatch, "type_ids"), "{:?}", report.issues);
+}
+
+#[test]
+fn class_def_and_class_data_resolve_to_names_and_flags() {
+    let parsed = dex_parser::parse_dex_bytes(BASE, &class_dex(&default_code())).unwrap();
+    assert_eq!(parsed.classes.len(), 1);
+    let class = &parsed.classes[0];
+    assert_eq!(class.descriptor, "LFoo;");
+    assert_eq!(class.access_flags, 0x401);
+    assert_eq!(class.superclass.as_deref(), Some("Ljava/lang/Object;"));
+    assert_eq!(class.interfaces, ["LIface;"]);
+    assert_eq!(class.source_file.as_deref(), Some("Foo.java"));
+    assert_eq!(class.class_data_error, None);
+
+    let fields = |fields: &[dex_parser::ParsedField]| -> Vec<(u32, String, String, u32)> {
+        fields.iter().map(|f| (f.field_idx, f.name.clone(), f.type_name.clone(), f.access_flags)).collect()
+    };
+    assert_eq!(fields(&class.static_fields), [(0, "MAX".to_string(), "I".to_string(), 0x19)]);
+    assert_eq!(fields(&class.instance_fields), [(1, "count".to_string(), "I".to_string(), 0x2)]);
+
+    let init = &class.direct_methods[..];
+    assert_eq!(init.len(), 1);
+    assert_eq!((init[0].method_idx, init[0].name.as_str(), init[0].signature.as_str(), init[0].access_flags), (0, "<init>", "()V", 0x10001));
+    let run = &class.virtual_methods[..];
+    assert_eq!(run.len(), 1);
+    assert_eq!((run[0].method_idx, run[0].name.as_str(), run[0].access_flags), (1, "run", 0x401));
+
+    // Methods of the class are defined, Object.<init> is only referenced
+    let defined: Vec<bool> = parsed.methods.iter().map(|m| m.is_defined()).collect();
+    assert_eq!(defined, [true, true, false]);
+    assert_eq!(parsed.methods[0].access_flags, Some(0x10001));
+    assert_eq!(parsed.code_item(0).map(|code| code.insns.len()), Some(4));
+    assert!(parsed.code_item(1).is_none(), "run is abstract");
+    assert!(parsed.code_item(2).is_none());
+}
+
+#[test]
+fn truncated_class_data_keeps_the_members_before_the_error() {
+    let mut dex = class_dex(&default_code());
+    // virtual_methods_size, the fourth uleb128 of class_data: 127 methods, far more than
+    // the bytes left in the file
+    let class_data = u32_at(&dex, u32_at(&dex, 0x64) as usize + 24) as usize;
+    dex[class_data + 3] = 0x7f;
+
+    let parsed = dex_parser::parse_dex_bytes(BASE, &dex).unwrap();
+    let class = &parsed.classes[0];
+    assert!(class.class_data_error.is_some());
+    assert_eq!(class.static_fields[0].name, "MAX");
+    assert_eq!(class.instance_fields[0].name, "count");
+    assert_eq!(class.direct_methods[0].name, "<init>");
+    assert_eq!(class.virtual_methods[0].name, "run");
+    assert!(class.virtual_methods.len() < 127);
+    assert!(parsed.code_item(0).is_some());
+}
+
+/// The CodeItem of `<init>` and its `code_error` in a dex built around `code`.
+fn init_code(code: &[u8]) -> (Option<dex_parser::dex_structs::CodeItem>, Option<String>) {
+    let parsed = dex_parser::parse_dex_bytes(BASE, &class_dex(code)).unwrap();
+    let error = parsed.classes[0].direct_methods[0].code_error.clone();
+    (parsed.code_item(0).cloned(), error)
+}
+
+#[test]
+fn tries_after_an_odd_insns_size_skip_the_padding() {
+    // nop; nop; return-void, the first two covered by a try catching Object
+    let mut handlers = Vec::new();
+    uleb128(&mut handlers, 1);
+    sleb128(&mut handlers, 1);
+    uleb128(&mut handlers, 3); // Ljava/lang/Object;
+    uleb128(&mut handlers, 2);
+    let (code, error) = init_code(&code_item(1, 1, &[0, 0, 0x000e], &[(0, 2, 1)], &handlers));
+
+    assert_eq!(error, None);
+    let code = code.unwrap();
+    assert_eq!(code.insns, [0, 0, 0x000e]);
+    assert_eq!(code.tries.len(), 1);
+    assert_eq!((code.tries[0].start_addr, code.tries[0].insn_count), (0, 2));
+    let handler = code.handler_of(&code.tries[0]).unwrap();
+    assert_eq!(handler.offset, 1);
+    let typed: Vec<(u32, u32)> = handler.handlers.iter().map(|h| (h.type_idx, h.addr)).collect();
+    assert_eq!(typed, [(3, 2)]);
+    assert_eq!(handler.catch_all_addr, None);
+}
+
+#[test]
+fn size_of_zero_or_less_adds_a_catch_all() {
+    let mut handlers = Vec::new();
+    uleb128(&mut handlers, 2);
+    // At 1: one typed handler and a catch-all
+    sleb128(&mut handlers, -1);
+    uleb128(&mut handlers, 3);
+    uleb128(&mut handlers, 2);
+    uleb128(&mut handlers, 3);
+    // At 5: only a catch-all, `finally`
+    sleb128(&mut handlers, 0);
+    uleb128(&mut handlers, 3);
+    let (code, error) = init_code(&code_item(1, 1, &[0, 0, 0, 0x000e], &[(0, 1, 1), (1, 1, 5)], &handlers));
+
+    assert_eq!(error, None);
+    let code = code.unwrap();
+    assert_eq!(code.handlers.len(), 2);
+    let first = code.handler_of(&code.tries[0]).unwrap();
+    assert_eq!(first.handlers.len(), 1);
+    assert_eq!(first.catch_all_addr, Some(3));
+    let finally = code.handler_of(&code.tries[1]).unwrap();
+    assert_eq!(finally.offset, 5);
+    assert!(finally.handlers.is_empty());
+    assert_eq!(finally.catch_all_addr, Some(3));
+}
+
+#[test]
+fn truncated_handler_list_keeps_the_insns() {
+    // One handler claiming far more type/addr pairs than the rest of the file holds
+    let mut handlers = Vec::new();
+    uleb128(&mut handlers, 1);
+    sleb128(&mut handlers, 0x10000);
+    let (code, error) = init_code(&code_item(1, 1, &[0, 0x000e], &[(0, 1, 1)], &handlers));
+
+    assert!(error.is_some());
+    let code = code.unwrap();
+    assert_eq!(code.insns, [0, 0x000e]);
+    assert!(code.tries.is_empty() && code.handlers.is_empty());
+}
@@ -0,0 +1,95 @@
+// Header reconstruction: wipe the header of a known dex and compare the rebuilt one.
+
+mod common;
+
+use common::*;
+use zygisk_rust_dex_unpacker::dex_parser;
+use zygisk_rust_dex_unpacker::dex_reconstruct::{self, Reconstruction, ReconstructionMethod};
+use zygisk_rust_dex_unpacker::dex_scanner::MAX_DEX_SIZE;
+use zygisk_rust_dex_unpacker::maps::MapRange;
+use zygisk_rust_dex_unpacker::memory_reader::SliceReader;
+
+const BASE: usize = 0x7100_0000_0000;
+const MAP_SIZE: usize = 0x2000;
+
+/// Every field a packer may zero, file_size to data_off
+const WIPED: std::ops::Range<usize> = 0x20..0x70;
+
+fn rebuild(dex: &[u8]) -> Option<Reconstruction> {
+    let mut memory = dex.to_vec();
+    memory.resize(MAP_SIZE, 0);
+    let ranges = [MapRange::new(BASE, MAP_SIZE, "rw-p", None)];
+    let header = memory[..0x70].try_into().unwrap();
+    dex_reconstruct::reconstruct(&SliceReader::new(BASE, &memory), BASE, header, &ranges, MAX_DEX_SIZE)
+}
+
+fn wiped(dex: &[u8]) -> Vec<u8> {
+    let mut wiped = dex.to_vec();
+    wiped[WIPED].fill(0);
+    wiped
+}
+
+#[test]
+fn map_list_restores_every_header_field() {
+    let dex = class_dex(&default_code());
+    let mut image = wiped(&dex);
+    let reconstruction = rebuild(&image).expect("no reconstruction");
+
+    assert_eq!(reconstruction.method, ReconstructionMethod::MapList);
+    assert_eq!(reconstruction.file_size, dex.len());
+    assert!(reconstruction.confidence >= 0.9, "confidence {}", reconstruction.confidence);
+
+    reconstruction.apply(&mut image);
+    assert_eq!(image[WIPED], dex[WIPED]);
+    let parsed = dex_parser::parse_dex_bytes(BASE, &image).unwrap();
+    assert_eq!(parsed.classes.len(), 1);
+    assert!(parsed.validation.is_clean());
+}
+
+#[test]
+fn garbage_file_size_is_replaced_by_the_map_list_extent() {
+    let dex = class_dex(&default_code());
+    let mut image = wiped(&dex);
+    put_u32(&mut image, FILE_SIZE, 0xdead_0000);
+    let reconstruction = rebuild(&image).expect("no reconstruction");
+
+    assert_eq!(reconstruction.method, ReconstructionMethod::MapList);
+    assert_eq!(reconstruction.file_size, dex.len());
+    reconstruction.apply(&mut image);
+    assert_eq!(image[WIPED], dex[WIPED]);
+}
+
+#[test]
+fn string_ids_rebuild_the_id_tables_without_a_map_list() {
+    let dex = class_dex(&default_code());
+    let mut image = wiped(&dex);
+    let map_off = u32_at(&dex, MAP_OFF) as usize;
+    image[map_off..].fill(0);
+    let reconstruction = rebuild(&image).expect("no reconstruction");
+
+    assert_eq!(reconstruction.method, ReconstructionMethod::StringIds);
+    assert!(reconstruction.confidence <= 0.5, "confidence {}", reconstruction.confidence);
+    // Only a lower bound: up to the end of the last string, "run"
+    let string_ids = u32_at(&dex, STRING_IDS_OFF) as usize;
+    let strings_end = u32_at(&dex, string_ids + (STRINGS.len() - 1) * 4) as usize + 1 + "run".len() + 1;
+    let file_size = reconstruction.file_size;
+    assert!((strings_end..=dex.len()).contains(&file_size), "file_size 0x{:x}", file_size);
+
+    reconstruction.apply(&mut image);
+    for field in [STRING_IDS_SIZE, STRING_IDS_OFF, 0x40, TYPE_IDS_OFF] {
+        assert_eq!(u32_at(&image, field), u32_at(&dex, field), "header field 0x{:x}", field);
+    }
+}
+
+#[test]
+fn map_list_with_a_section_past_the_mapping_is_not_trusted() {
+    let dex = class_dex(&default_code());
+    let mut image = wiped(&dex);
+    // type_ids item, the third of the map_list: size far beyond the mapping
+    let map_off = u32_at(&dex, MAP_OFF) as usize;
+    put_u32(&mut image, map_off + 4 + 2 * 12 + 4, 0x1000_0000);
+    let reconstruction = rebuild(&image).expect("no reconstruction");
+
+    assert_eq!(reconstruction.method, ReconstructionMethod::StringIds);
+    assert!(reconstruction.file_size <= MAP_SIZE);
+}
@@ -0,0 +1,159 @@
+// Offline snapshots: a minimal ELF64 core and raw images in both layouts, written to temp files.
+
+use std::path::{Path, PathBuf};
+use zygisk_rust_dex_unpacker::dex_scanner::PointerWidth;
+use zygisk_rust_dex_unpacker::memory_reader::MemoryReader;
+use zygisk_rust_dex_unpacker::snapshot::{self, RawLayout};
+
+const HEAP: usize = 0x7000_0000;
+const LIB: usize = 0x7000_2000;
+const LIB_PATH: &str = "/system/lib64/libfoo.so";
+
+fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("dexunpack-snapshot-{}-{}", std::process::id(), name));
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+fn read(reader: &dyn MemoryReader, addr: usize, len: usize) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    match reader.read_memory(addr, &mut buf) {
+        Ok(n) if n == len => Some(buf),
+        _ => None,
+    }
+}
+
+/// Program header of an ELF64 core.
+fn phdr(p_type: u32, flags: u32, offset: u64, vaddr: u64, filesz: u64, memsz: u64) -> Vec<u8> {
+    let mut ph = vec![0u8; 56];
+    ph[0x00..0x04].copy_from_slice(&p_type.to_le_bytes());
+    ph[0x04..0x08].copy_from_slice(&flags.to_le_bytes());
+    ph[0x08..0x10].copy_from_slice(&offset.to_le_bytes());
+    ph[0x10..0x18].copy_from_slice(&vaddr.to_le_bytes());
+    ph[0x20..0x28].copy_from_slice(&filesz.to_le_bytes());
+    ph[0x28..0x30].copy_from_slice(&memsz.to_le_bytes());
+    ph
+}
+
+/// A core with a PT_NOTE holding NT_FILE, a rw heap, a library whose second page the kernel
+/// left out (memsz > filesz) and an empty PT_LOAD.
+fn core_file() -> Vec<u8> {
+    let mut desc = Vec::new();
+    for word in [1u64, 0x1000, LIB as u64, LIB as u64 + 0x2000, 2] {
+        desc.extend_from_slice(&word.to_le_bytes());
+    }
+    desc.extend_from_slice(LIB_PATH.as_bytes());
+    desc.push(0);
+    while desc.len() % 4 != 0 {
+        desc.push(0);
+    }
+    let mut note = Vec::new();
+    note.extend_from_slice(&5u32.to_le_bytes()); // namesz
+    note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    note.extend_from_slice(&0x4649_4c45u32.to_le_bytes()); // NT_FILE
+    note.extend_from_slice(b"CORE\0\0\0\0");
+    note.extend_from_slice(&desc);
+
+    let phnum = 4;
+    let note_off = 64 + phnum * 56;
+    let heap_off = 0x1000;
+    let lib_off = 0x2000;
+
+    let mut core = vec![0u8; 64];
+    core[0..4].copy_from_slice(b"\x7fELF");
+    core[4] = 2; // ELFCLASS64
+    core[5] = 1; // ELFDATA2LSB
+    core[6] = 1;
+    core[0x10..0x12].copy_from_slice(&4u16.to_le_bytes()); // ET_CORE
+    core[0x20..0x28].copy_from_slice(&64u64.to_le_bytes()); // phoff
+    core[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+    core[0x38..0x3a].copy_from_slice(&(phnum as u16).to_le_bytes());
+    core.extend(phdr(4, 0, note_off as u64, 0, note.len() as u64, 0));
+    core.extend(phdr(1, 6, heap_off, HEAP as u64, 0x1000, 0x1000)); // rw-
+    core.extend(phdr(1, 5, lib_off, LIB as u64, 0x1000, 0x2000)); // r-x
+    core.extend(phdr(1, 4, 0, 0x7000_8000, 0, 0));
+    core.extend(note);
+
+    core.resize(heap_off as usize, 0);
+    core.extend(std::iter::repeat_n(0xaa, 0x1000));
+    core.extend(std::iter::repeat_n(0xbb, 0x1000));
+    core
+}
+
+#[test]
+fn core_loads_become_ranges_named_from_nt_file() {
+    let path = temp_file("core", &core_file());
+    let snapshot = snapshot::open_core_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(snapshot.pointer_width, Some(PointerWidth::Bits64));
+    assert_eq!(snapshot.ranges.len(), 2, "the empty PT_LOAD is not a mapping");
+
+    let heap = &snapshot.ranges[0];
+    assert_eq!((heap.start(), heap.size(), heap.flags.as_str()), (HEAP, 0x1000, "rw-p"));
+    assert_eq!(heap.filename(), None);
+
+    let lib = &snapshot.ranges[1];
+    assert_eq!((lib.start(), lib.size(), lib.flags.as_str()), (LIB, 0x2000, "r-xp"));
+    assert_eq!(lib.filename(), Some(Path::new(LIB_PATH)));
+    assert_eq!(lib.offset, 0x2000, "page offset 2 times the 0x1000 page size");
+
+    assert_eq!(read(&snapshot.reader, HEAP + 0x10, 4), Some(vec![0xaa; 4]));
+    assert_eq!(read(&snapshot.reader, LIB + 0xff0, 0x10), Some(vec![0xbb; 0x10]));
+    assert_eq!(read(&snapshot.reader, LIB + 0x1000, 4), None, "pages past filesz are not in the core");
+}
+
+#[test]
+fn non_core_elf_is_rejected() {
+    let mut core = core_file();
+    core[0x10..0x12].copy_from_slice(&3u16.to_le_bytes()); // ET_DYN
+    let path = temp_file("dyn", &core);
+    let result = snapshot::open_core_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
+
+const MAPS: &str = "\
+1000-2000 rw-p 00000000 00:00 0 [anon:dalvik-main space]
+2000-3000 ---p 00000000 00:00 0
+3000-5000 r--p 00001000 fd:01 42 /data/app/base.apk
+";
+
+#[test]
+fn concatenated_image_packs_readable_maps_back_to_back() {
+    let mut image = vec![0x11u8; 0x1000];
+    image.extend(std::iter::repeat_n(0x33, 0x1800)); // the last 0x800 bytes of 3000-5000 are missing
+    let image_path = temp_file("concat.img", &image);
+    let maps_path = temp_file("concat.maps", MAPS.as_bytes());
+    let snapshot = snapshot::open_raw_image(&image_path, &maps_path, RawLayout::Concatenated).unwrap();
+    std::fs::remove_file(&image_path).unwrap();
+    std::fs::remove_file(&maps_path).unwrap();
+
+    assert_eq!(snapshot.pointer_width, None);
+    let starts: Vec<usize> = snapshot.ranges.iter().map(|r| r.start()).collect();
+    assert_eq!(starts, [0x1000, 0x3000], "unreadable maps are dropped");
+    assert_eq!(snapshot.ranges[1].filename(), Some(Path::new("/data/app/base.apk")));
+
+    assert_eq!(read(&snapshot.reader, 0x1ffc, 4), Some(vec![0x11; 4]));
+    assert_eq!(read(&snapshot.reader, 0x3000, 4), Some(vec![0x33; 4]));
+    assert_eq!(read(&snapshot.reader, 0x47fc, 4), Some(vec![0x33; 4]));
+    assert_eq!(read(&snapshot.reader, 0x4800, 4), None);
+}
+
+#[test]
+fn sparse_image_uses_addresses_as_file_offsets() {
+    let mut image = vec![0u8; 0x5000];
+    image[0x1000..0x2000].fill(0x11);
+    image[0x2000..0x3000].fill(0x22); // behind the ---p map, never read
+    image[0x3000..0x5000].fill(0x33);
+    let image_path = temp_file("sparse.img", &image);
+    let maps_path = temp_file("sparse.maps", MAPS.as_bytes());
+    let snapshot = snapshot::open_raw_image(&image_path, &maps_path, RawLayout::Sparse).unwrap();
+    std::fs::remove_file(&image_path).unwrap();
+    std::fs::remove_file(&maps_path).unwrap();
+
+    assert_eq!(snapshot.ranges.len(), 2);
+    assert_eq!(read(&snapshot.reader, 0x1000, 4), Some(vec![0x11; 4]));
+    assert_eq!(read(&snapshot.reader, 0x2000, 4), None);
+    assert_eq!(read(&snapshot.reader, 0x4ffc, 4), Some(vec![0x33; 4]));
+}
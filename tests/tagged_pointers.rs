@@ -0,0 +1,84 @@
+// Pointer scan over synthetic memory: a heap map holding a tagged pointer to a dex whose
+// magic was wiped, so only the pointer scan can find it.
+
+use zygisk_rust_dex_unpacker::dex_scanner::{self, ScanOptions, DEFAULT_POINTER_TAG_MASK};
+use zygisk_rust_dex_unpacker::maps::MapRange;
+use zygisk_rust_dex_unpacker::memory_reader::SliceReader;
+
+const BASE: usize = 0x7100_0000_0000;
+const HEAP_SIZE: usize = 0x1000;
+const DEX_ADDR: usize = BASE + HEAP_SIZE;
+const DEX_SIZE: usize = 0x200;
+/// Where the heap holds the pointer
+const POINTER_OFFSET: usize = 0x40;
+
+/// Heap map followed by a dex map, as one slice starting at `BASE`.
+fn memory(pointer: u64) -> (Vec<u8>, Vec<MapRange>) {
+    let mut memory = vec![0u8; HEAP_SIZE + DEX_SIZE];
+    memory[POINTER_OFFSET..POINTER_OFFSET + 8].copy_from_slice(&pointer.to_le_bytes());
+
+    let dex = &mut memory[HEAP_SIZE..];
+    let mut put = |off: usize, value: u32| dex[off..off + 4].copy_from_slice(&value.to_le_bytes());
+    put(0x20, DEX_SIZE as u32); // file_size
+    put(0x24, 0x70); // header_size
+    put(0x28, 0x1234_5678); // endian_tag
+    put(0x34, 0x100); // map_off
+    put(0x3c, 0x70); // string_ids_off
+    put(0x100, 1); // map_list size
+
+    let ranges = vec![
+        MapRange::new(BASE, HEAP_SIZE, "rw-p", Some("[anon:scudo:primary]".into())),
+        MapRange::new(DEX_ADDR, DEX_SIZE, "rw-p", None),
+    ];
+    (memory, ranges)
+}
+
+fn scan(pointer: u64, tag_mask: u64) -> Vec<dex_scanner::DexFileResult> {
+    let (memory, ranges) = memory(pointer);
+    let options = ScanOptions { magic_scan: false, pointer_tag_mask: tag_mask, ..Default::default() };
+    dex_scanner::scan_memory(&SliceReader::new(BASE, &memory), &ranges, &options)
+}
+
+#[test]
+fn strip_pointer_tag_clears_only_masked_bits() {
+    let tagged = 0xb400_7100_0000_1000;
+    assert_eq!(dex_scanner::strip_pointer_tag(tagged, DEFAULT_POINTER_TAG_MASK), 0x7100_0000_1000);
+    assert_eq!(dex_scanner::strip_pointer_tag(tagged, 0), tagged);
+    assert_eq!(dex_scanner::strip_pointer_tag(0x0a00_7100_0000_1000, 0x0f00_0000_0000_0000), 0x7100_0000_1000);
+}
+
+#[test]
+fn untagged_pointer_is_found() {
+    let results = scan(DEX_ADDR as u64, DEFAULT_POINTER_TAG_MASK);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].addr, DEX_ADDR);
+    assert_eq!(results[0].source, "PointerScan");
+}
+
+#[test]
+fn scudo_tagged_pointer_is_found_and_recorded() {
+    let tagged = 0xb400_0000_0000_0000 | DEX_ADDR as u64;
+    let results = scan(tagged, DEFAULT_POINTER_TAG_MASK);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].addr, DEX_ADDR);
+
+    let pointer = results[0].referenced_from.as_ref().expect("pointer scan hit without a reference");
+    assert_eq!(pointer.address, format!("0x{:x}", BASE + POINTER_OFFSET));
+    assert_eq!(pointer.value, format!("0x{:x}", tagged));
+    assert_eq!(pointer.map.as_ref().and_then(|m| m.anon_name.as_deref()), Some("scudo:primary"));
+}
+
+#[test]
+fn tagged_pointer_is_missed_without_mask() {
+    let tagged = 0xb400_0000_0000_0000 | DEX_ADDR as u64;
+    assert!(scan(tagged, 0).is_empty());
+}
+
+#[test]
+fn mte_only_mask_keeps_other_top_byte_bits() {
+    let mte_tagged = 0x0a00_0000_0000_0000 | DEX_ADDR as u64;
+    let tbi_tagged = 0xb400_0000_0000_0000 | DEX_ADDR as u64;
+    let mte_mask = 0x0f00_0000_0000_0000;
+    assert_eq!(scan(mte_tagged, mte_mask).len(), 1);
+    assert!(scan(tbi_tagged, mte_mask).is_empty());
+}